@@ -0,0 +1,67 @@
+use std::path::PathBuf;
+
+use clap::{Parser, ValueEnum};
+
+#[derive(Clone, Copy, Debug, PartialEq, ValueEnum)]
+pub enum Format {
+    Text,
+    #[value(alias = "md")]
+    Markdown,
+    Json,
+    Epub,
+}
+
+#[derive(Parser, Debug)]
+#[command(name = "rwiki", version, about = "A distraction free Wikipedia reader for the terminal")]
+pub struct Args {
+    /// Article to open on startup, optionally with a #section
+    pub title: Option<String>,
+
+    /// Start with the results of a search
+    #[arg(short, long, value_name = "QUERY", conflicts_with_all = ["title", "random"])]
+    pub search: Option<String>,
+
+    /// Wikipedia language edition to read
+    #[arg(short, long, value_name = "CODE", default_value = "en", conflicts_with = "site")]
+    pub lang: String,
+
+    /// MediaWiki site or api.php URL to read from instead of Wikipedia, e.g. https://wiki.archlinux.org/api.php
+    #[arg(long, value_name = "URL")]
+    pub site: Option<String>,
+
+    /// Config file to use instead of ~/.config/rmus/rmus.conf
+    #[arg(short, long, value_name = "PATH")]
+    pub config: Option<PathBuf>,
+
+    /// Read only articles saved in the local cache, without using the network
+    #[arg(long)]
+    pub offline: bool,
+
+    /// Open a random article
+    #[arg(short, long, conflicts_with = "title")]
+    pub random: bool,
+
+    /// Print the article as plain text instead of opening the reader (default when stdout is not a terminal)
+    #[arg(short, long)]
+    pub print: bool,
+
+    /// Line width for printed output, defaults to the terminal width or 80
+    #[arg(short, long, value_name = "COLUMNS")]
+    pub width: Option<u16>,
+
+    /// Only show the named section of the article
+    #[arg(long, value_name = "NAME", requires = "title")]
+    pub section: Option<String>,
+
+    /// Print the article in this format instead of opening the reader
+    #[arg(short, long, value_enum)]
+    pub format: Option<Format>,
+
+    /// Write the printed article to a file instead of stdout, required for epub
+    #[arg(short, long, value_name = "PATH")]
+    pub output: Option<PathBuf>,
+
+    /// Reading list file with one article title or URL per line, printed or exported together
+    #[arg(short = 'L', long, value_name = "PATH", conflicts_with_all = ["title", "random", "search", "section"])]
+    pub list: Option<PathBuf>,
+}
@@ -0,0 +1,371 @@
+use std::{collections::{hash_map::DefaultHasher, HashSet}, hash::{Hash, Hasher}, io::{Cursor, Write}, path::Path, sync::Arc, time::{SystemTime, UNIX_EPOCH}};
+
+use anyhow::{anyhow, Result};
+use tokio::{sync::Semaphore, task::JoinSet};
+use zip::{write::SimpleFileOptions, CompressionMethod, ZipWriter};
+
+use crate::{civil_date, images, site::Site, text::{InlineStyle, LineKind, TextLine}, ContentBlock, Reference};
+
+const IMAGE_WIDTH: u32 = 800;
+
+const STYLE: &str = "body { font-family: serif; line-height: 1.4; }
+figure { margin: 1em 0; text-align: center; }
+figure img { max-width: 100%; }
+figcaption { font-size: 0.9em; font-style: italic; }
+table { border-collapse: collapse; margin: 1em 0; }
+th, td { border: 1px solid #999; padding: 0.2em 0.4em; }
+pre { white-space: pre-wrap; font-size: 0.85em; }
+.infobox { border: 1px solid #999; padding: 0 0.5em; margin: 1em 0; font-size: 0.9em; }
+.math { text-align: center; }
+";
+
+pub struct Article {
+    title: String,
+    body: String,
+    toc: Vec<(usize, String, String)>,
+    images: Vec<(String, String)>,
+}
+
+fn escape(text: &str) -> String {
+    text.replace('&', "&amp;").replace('<', "&lt;").replace('>', "&gt;").replace('"', "&quot;")
+}
+
+fn image_name(url: &str) -> String {
+    let mut hasher = DefaultHasher::new();
+    url.hash(&mut hasher);
+    let ext = url.rsplit('.').next().map(str::to_ascii_lowercase)
+        .filter(|ext| matches!(ext.as_str(), "jpg" | "jpeg" | "png" | "gif" | "svg"))
+        .unwrap_or_else(|| "png".to_string());
+    format!("{:016x}.{}", hasher.finish(), ext)
+}
+
+fn media_type(name: &str) -> &'static str {
+    match name.rsplit('.').next() {
+        Some("jpg" | "jpeg") => "image/jpeg",
+        Some("gif") => "image/gif",
+        Some("svg") => "image/svg+xml",
+        _ => "image/png",
+    }
+}
+
+fn inline(spans: &[(String, InlineStyle)], site: &Site, title: &str) -> String {
+    let mut out = String::new();
+    for (text, style) in spans {
+        let mut html = escape(text);
+        if style.code { html = format!("<code>{}</code>", html); }
+        if style.italic { html = format!("<i>{}</i>", html); }
+        if style.bold { html = format!("<b>{}</b>", html); }
+        if style.strike { html = format!("<s>{}</s>", html); }
+        if let Some(url) = style.link.as_deref().and_then(|href| site.link_url(title, href)) {
+            html = format!("<a href=\"{}\">{}</a>", escape(&url), html);
+        }
+        out.push_str(&html);
+    }
+    out.trim().to_string()
+}
+
+impl Article {
+    pub fn new(site: &Site, title: &str, infobox: &str, blocks: &[ContentBlock], references: &[Reference]) -> Self {
+        let mut article = Self { title: title.to_string(), body: format!("<h1>{}</h1>\n", escape(title)), toc: Vec::new(), images: Vec::new() };
+        if !infobox.is_empty() {
+            article.body.push_str("<aside class=\"infobox\">\n");
+            for line in infobox.lines().map(str::trim).filter(|l| !l.is_empty()) {
+                article.body.push_str(&format!("<p>{}</p>\n", escape(line)));
+            }
+            article.body.push_str("</aside>\n");
+        }
+
+        for block in blocks {
+            match block {
+                ContentBlock::Text(lines) => article.push_lines(site, lines),
+                ContentBlock::Image(figure) => {
+                    let url = images::thumbnail_url(&figure.url, IMAGE_WIDTH, figure.file_width).unwrap_or_else(|| figure.url.clone());
+                    let name = image_name(&url);
+                    let alt = figure.caption.as_deref().or(figure.file.as_deref()).unwrap_or("");
+                    article.body.push_str(&format!("<figure><img src=\"images/{}\" alt=\"{}\"/>", name, escape(alt)));
+                    if let Some(caption) = &figure.caption {
+                        article.body.push_str(&format!("<figcaption>{}</figcaption>", escape(caption)));
+                    }
+                    article.body.push_str("</figure>\n");
+                    article.images.push((url, name));
+                }
+                ContentBlock::Table(table) => {
+                    article.body.push_str("<table>\n");
+                    if let Some(caption) = &table.caption {
+                        article.body.push_str(&format!("<caption>{}</caption>\n", escape(caption)));
+                    }
+                    for (i, row) in table.grid.iter().enumerate() {
+                        let tag = if i == 0 { "th" } else { "td" };
+                        let cells: String = row.iter().map(|cell| format!("<{0}>{1}</{0}>", tag, escape(cell))).collect();
+                        article.body.push_str(&format!("<tr>{}</tr>\n", cells));
+                    }
+                    article.body.push_str("</table>\n");
+                }
+                ContentBlock::Code(code) => article.body.push_str(&format!("<pre><code>{}</code></pre>\n", escape(&code.source))),
+                ContentBlock::Math { tex, unicode } => match unicode {
+                    Some(text) => article.body.push_str(&format!("<p class=\"math\">{}</p>\n", escape(text))),
+                    None => article.body.push_str(&format!("<pre class=\"math\">{}</pre>\n", escape(tex))),
+                },
+            }
+        }
+
+        if !references.is_empty() {
+            article.toc.push((2, "References".to_string(), "references".to_string()));
+            article.body.push_str("<h2 id=\"references\">References</h2>\n<ul class=\"references\">\n");
+            for reference in references {
+                article.body.push_str(&format!("<li>[{}] {}", escape(&reference.label), escape(&reference.text)));
+                if let Some(url) = &reference.url {
+                    article.body.push_str(&format!(" <a href=\"{0}\">{0}</a>", escape(url)));
+                }
+                article.body.push_str("</li>\n");
+            }
+            article.body.push_str("</ul>\n");
+        }
+        article
+    }
+
+    pub fn title(&self) -> &str {
+        &self.title
+    }
+
+    fn push_lines(&mut self, site: &Site, lines: &[TextLine]) {
+        let mut lists: Vec<(usize, &'static str)> = Vec::new();
+        for line in lines {
+            let text = inline(&line.spans, site, &self.title);
+            match &line.kind {
+                LineKind::ListItem { marker, .. } if marker.is_empty() && !lists.is_empty() => self.body.push_str(&format!("<br/>{}", text)),
+                LineKind::ListItem { indent, marker } if !marker.is_empty() => {
+                    let tag = if marker.starts_with(|c: char| c.is_ascii_digit()) { "ol" } else { "ul" };
+                    close_lists(&mut self.body, &mut lists, Some(*indent));
+                    if let Some(&(current, open)) = lists.last() && current == *indent && open != tag {
+                        close_lists(&mut self.body, &mut lists, current.checked_sub(1));
+                    }
+                    if lists.last().is_some_and(|&(current, _)| current == *indent) {
+                        self.body.push_str(&format!("</li>\n<li>{}", text));
+                    } else {
+                        self.body.push_str(&format!("<{}>\n<li>{}", tag, text));
+                        lists.push((*indent, tag));
+                    }
+                }
+                LineKind::Header(level) => {
+                    close_lists(&mut self.body, &mut lists, None);
+                    let anchor = format!("s{}", self.toc.len() + 1);
+                    self.body.push_str(&format!("<h{0} id=\"{1}\">{2}</h{0}>\n", (*level).clamp(2, 6), anchor, text));
+                    self.toc.push((*level, line.plain().trim().to_string(), anchor));
+                }
+                LineKind::Quote(_) => {
+                    close_lists(&mut self.body, &mut lists, None);
+                    self.body.push_str(&format!("<blockquote><p>{}</p></blockquote>\n", text));
+                }
+                _ => {
+                    close_lists(&mut self.body, &mut lists, None);
+                    self.body.push_str(&format!("<p>{}</p>\n", text));
+                }
+            }
+        }
+        close_lists(&mut self.body, &mut lists, None);
+    }
+}
+
+fn close_lists(body: &mut String, lists: &mut Vec<(usize, &'static str)>, above: Option<usize>) {
+    while let Some(&(indent, tag)) = lists.last() {
+        if above.is_some_and(|limit| indent <= limit) { break; }
+        body.push_str(&format!("</li></{}>\n", tag));
+        lists.pop();
+    }
+}
+
+fn nested_toc(entries: &[(usize, String, String)], file: &str) -> String {
+    let mut out = String::from("<ol>\n");
+    let mut i = 0;
+    while i < entries.len() {
+        let (level, name, anchor) = &entries[i];
+        let end = entries[i + 1..].iter().position(|(l, _, _)| l <= level).map_or(entries.len(), |p| i + 1 + p);
+        out.push_str(&format!("<li><a href=\"{}#{}\">{}</a>", file, anchor, escape(name)));
+        if end > i + 1 {
+            out.push_str(&nested_toc(&entries[i + 1..end], file));
+        }
+        out.push_str("</li>\n");
+        i = end;
+    }
+    out.push_str("</ol>\n");
+    out
+}
+
+fn timestamp() -> String {
+    let secs = SystemTime::now().duration_since(UNIX_EPOCH).map(|d| d.as_secs() as i64).unwrap_or(0);
+    let (year, month, day) = civil_date(secs.div_euclid(86400));
+    let time = secs.rem_euclid(86400);
+    format!("{:04}-{:02}-{:02}T{:02}:{:02}:{:02}Z", year, month, day, time / 3600, time % 3600 / 60, time % 60)
+}
+
+fn xhtml(title: &str, lang: &str, body: &str) -> String {
+    format!(
+        "<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n<!DOCTYPE html>\n<html xmlns=\"http://www.w3.org/1999/xhtml\" xmlns:epub=\"http://www.idpf.org/2007/ops\" xml:lang=\"{1}\" lang=\"{1}\">\n<head><title>{0}</title><link rel=\"stylesheet\" href=\"style.css\"/></head>\n<body>\n{2}</body>\n</html>\n",
+        escape(title), escape(lang), body,
+    )
+}
+
+async fn download(client: &reqwest::Client, site: &Site, url: &str, name: &str) -> Result<Vec<u8>> {
+    if site.offline { return Err(anyhow!("images are not available offline")); }
+    let bytes = client.get(url).send().await?.error_for_status()?.bytes().await?.to_vec();
+    if url.to_ascii_lowercase().ends_with(name.rsplit('.').next().unwrap_or_default()) {
+        return Ok(bytes);
+    }
+    tokio::task::spawn_blocking(move || -> Result<Vec<u8>> {
+        let mut png = Vec::new();
+        image::load_from_memory(&bytes)?.write_to(&mut Cursor::new(&mut png), image::ImageFormat::Png)?;
+        Ok(png)
+    }).await?
+}
+
+fn remove_image(body: &str, name: &str) -> String {
+    let needle = format!("<img src=\"images/{}\"", name);
+    let mut out = body.to_string();
+    while let Some(start) = out.find(&needle) {
+        let end = out[start..].find("/>").map_or(out.len(), |e| start + e + 2);
+        out.replace_range(start..end, "");
+    }
+    out
+}
+
+pub async fn write(client: &reqwest::Client, site: &Site, book_title: &str, articles: &[Article], path: &Path, limit: Arc<Semaphore>, progress: impl Fn(usize, usize)) -> Result<()> {
+    if articles.is_empty() { return Err(anyhow!("no articles to export")); }
+
+    let mut seen = HashSet::new();
+    let mut downloads = JoinSet::new();
+    for (i, (url, name)) in articles.iter().flat_map(|a| &a.images).filter(|(_, name)| seen.insert(name.clone())).enumerate() {
+        let (client, site, limit, url, name) = (client.clone(), site.clone(), limit.clone(), url.clone(), name.clone());
+        downloads.spawn(async move {
+            let bytes = match limit.acquire().await {
+                Ok(_permit) => download(&client, &site, &url, &name).await,
+                Err(e) => Err(e.into()),
+            };
+            (i, name, bytes)
+        });
+    }
+    let total = downloads.len();
+    let mut images = Vec::new();
+    let mut failed = Vec::new();
+    while let Some(result) = downloads.join_next().await {
+        match result? {
+            (i, name, Ok(bytes)) => images.push((i, name, bytes)),
+            (_, name, Err(_)) => failed.push(name),
+        }
+        progress(images.len() + failed.len(), total);
+    }
+    images.sort_by_key(|(i, _, _)| *i);
+
+    let stored = SimpleFileOptions::default().compression_method(CompressionMethod::Stored);
+    let deflated = SimpleFileOptions::default().compression_method(CompressionMethod::Deflated);
+    let mut zip = ZipWriter::new(Cursor::new(Vec::new()));
+    zip.start_file("mimetype", stored)?;
+    zip.write_all(b"application/epub+zip")?;
+    zip.start_file("META-INF/container.xml", deflated)?;
+    zip.write_all(b"<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n<container version=\"1.0\" xmlns=\"urn:oasis:names:tc:opendocument:xmlns:container\">\n<rootfiles><rootfile full-path=\"OEBPS/content.opf\" media-type=\"application/oebps-package+xml\"/></rootfiles>\n</container>\n")?;
+    zip.start_file("OEBPS/style.css", deflated)?;
+    zip.write_all(STYLE.as_bytes())?;
+
+    let mut toc = String::new();
+    let mut manifest = String::new();
+    let mut spine = String::new();
+    let mut hasher = DefaultHasher::new();
+    site.base.hash(&mut hasher);
+    for (i, article) in articles.iter().enumerate() {
+        let file = format!("article{}.xhtml", i + 1);
+        let body = failed.iter().fold(article.body.clone(), |body, name| remove_image(&body, name));
+        zip.start_file(format!("OEBPS/{}", file), deflated)?;
+        zip.write_all(xhtml(&article.title, &site.lang, &body).as_bytes())?;
+
+        toc.push_str(&format!("<li><a href=\"{}\">{}</a>", file, escape(&article.title)));
+        if !article.toc.is_empty() {
+            toc.push_str(&nested_toc(&article.toc, &file));
+        }
+        toc.push_str("</li>\n");
+        manifest.push_str(&format!("<item id=\"article{}\" href=\"{}\" media-type=\"application/xhtml+xml\"/>\n", i + 1, file));
+        spine.push_str(&format!("<itemref idref=\"article{}\"/>\n", i + 1));
+        article.title.hash(&mut hasher);
+    }
+
+    for (i, (_, name, bytes)) in images.iter().enumerate() {
+        zip.start_file(format!("OEBPS/images/{}", name), stored)?;
+        zip.write_all(bytes)?;
+        manifest.push_str(&format!("<item id=\"image{}\" href=\"images/{}\" media-type=\"{}\"/>\n", i + 1, name, media_type(name)));
+    }
+
+    let nav = format!("<nav epub:type=\"toc\" id=\"toc\">\n<h1>Contents</h1>\n<ol>\n{}</ol>\n</nav>\n", toc);
+    zip.start_file("OEBPS/nav.xhtml", deflated)?;
+    zip.write_all(xhtml("Contents", &site.lang, &nav).as_bytes())?;
+
+    let source = match articles {
+        [article] => format!("<dc:source>{}</dc:source>\n", escape(&site.page_url(&article.title))),
+        _ => String::new(),
+    };
+    let opf = format!(
+        "<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n<package xmlns=\"http://www.idpf.org/2007/opf\" version=\"3.0\" unique-identifier=\"book-id\">\n<metadata xmlns:dc=\"http://purl.org/dc/elements/1.1/\">\n<dc:identifier id=\"book-id\">urn:rwiki:{:016x}</dc:identifier>\n<dc:title>{}</dc:title>\n<dc:language>{}</dc:language>\n{}<meta property=\"dcterms:modified\">{}</meta>\n</metadata>\n<manifest>\n<item id=\"nav\" href=\"nav.xhtml\" media-type=\"application/xhtml+xml\" properties=\"nav\"/>\n<item id=\"style\" href=\"style.css\" media-type=\"text/css\"/>\n{}</manifest>\n<spine>\n<itemref idref=\"nav\"/>\n{}</spine>\n</package>\n",
+        hasher.finish(), escape(book_title), escape(&site.lang), source, timestamp(), manifest, spine,
+    );
+    zip.start_file("OEBPS/content.opf", deflated)?;
+    zip.write_all(opf.as_bytes())?;
+
+    let bytes = zip.finish()?.into_inner();
+    tokio::fs::write(path, bytes).await.map_err(|e| anyhow!("could not write {}: {}", path.display(), e))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Read;
+    use clap::Parser;
+    use crate::{cli::Args, http_client, text::rich_lines, Figure};
+
+    fn balanced(xml: &str) -> bool {
+        let mut open = Vec::new();
+        for tag in xml.split('<').skip(1).map(|t| &t[..t.find('>').unwrap_or(t.len())]) {
+            if tag.starts_with(['?', '!']) || tag.ends_with('/') { continue; }
+            let name = tag.trim_start_matches('/').split_whitespace().next().unwrap_or_default();
+            match tag.starts_with('/') {
+                true if open.pop() != Some(name) => return false,
+                true => {}
+                false => open.push(name),
+            }
+        }
+        open.is_empty()
+    }
+
+    #[tokio::test]
+    async fn failed_images_are_dropped_from_the_book_and_its_manifest() {
+        let site = Site::from_args(&Args::parse_from(["rwiki", "--offline"]));
+        let figure = Figure { url: "https://upload.wikimedia.org/wikipedia/commons/a/ab/Map.png".to_string(), file: Some("Map.png".to_string()), caption: Some("A <b> map".to_string()), width: 300, height: 200, file_width: 300 };
+        let blocks = [
+            ContentBlock::Text(rich_lines("<p>Intro</p><h2>Places</h2><ul><li>One</li><li>Two</li></ul>")),
+            ContentBlock::Image(figure),
+        ];
+        let article = Article::new(&site, "Test", "", &blocks, &[]);
+        assert_eq!(article.images.len(), 1);
+        let path = std::env::temp_dir().join(format!("rwiki-test-{}.epub", std::process::id()));
+        write(&http_client(), &site, "Test", &[article], &path, Arc::new(Semaphore::new(1)), |_, _| {}).await.unwrap();
+        let mut zip = zip::ZipArchive::new(std::fs::File::open(&path).unwrap()).unwrap();
+        let _ = std::fs::remove_file(&path);
+        let mut read = |name: &str| {
+            let mut content = String::new();
+            zip.by_name(name).unwrap().read_to_string(&mut content).unwrap();
+            content
+        };
+        let chapter = read("OEBPS/article1.xhtml");
+        assert!(!chapter.contains("<img") && chapter.contains("<figure><figcaption>A &lt;b&gt; map</figcaption></figure>"));
+        assert!(balanced(&chapter) && balanced(&read("OEBPS/nav.xhtml")));
+        let opf = read("OEBPS/content.opf");
+        let mut manifest: Vec<String> = opf.split(" href=\"").skip(1).map(|s| format!("OEBPS/{}", &s[..s.find('"').unwrap()])).collect();
+        manifest.sort();
+        let mut files: Vec<String> = zip.file_names().filter(|n| n.starts_with("OEBPS/") && *n != "OEBPS/content.opf").map(str::to_string).collect();
+        files.sort();
+        assert_eq!(manifest, files);
+    }
+
+    #[test]
+    fn removing_an_image_keeps_the_markup_around_it() {
+        let body = "<figure><img src=\"images/a.png\" alt=\"x\"/><figcaption>A</figcaption></figure>\n<figure><img src=\"images/b.png\" alt=\"\"/></figure>\n<figure><img src=\"images/a.png\" alt=\"\"/></figure>\n";
+        assert_eq!(remove_image(body, "a.png"), "<figure><figcaption>A</figcaption></figure>\n<figure><img src=\"images/b.png\" alt=\"\"/></figure>\n<figure></figure>\n");
+    }
+}
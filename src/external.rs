@@ -0,0 +1,148 @@
+use std::{env, fs::OpenOptions, io::{self, Write}, path::{Path, PathBuf}, process::{Command, Stdio}, time::{SystemTime, UNIX_EPOCH}};
+#[cfg(unix)]
+use std::os::unix::fs::OpenOptionsExt;
+
+fn command(line: &str, arg: Option<&str>) -> Option<Command> {
+    let mut parts = line.split_whitespace().map(str::to_string);
+    let mut command = Command::new(parts.next()?);
+    let mut args: Vec<String> = parts.collect();
+    if let Some(arg) = arg {
+        match args.iter_mut().find(|a| a.contains("%s")) {
+            Some(a) => *a = a.replace("%s", arg),
+            None => args.push(arg.to_string()),
+        }
+    }
+    command.args(args);
+    Some(command)
+}
+
+fn from_env(names: &[&str], fallback: &str) -> String {
+    names.iter()
+        .filter_map(|name| env::var(name).ok())
+        .find(|value| !value.trim().is_empty())
+        .unwrap_or_else(|| fallback.to_string())
+}
+
+const TERMINAL_BROWSERS: &[&str] = &["w3m", "lynx", "links", "links2", "elinks", "browsh", "carbonyl"];
+
+fn browser() -> String {
+    env::var("BROWSER").ok()
+        .and_then(|b| b.split(':').map(str::trim).find(|c| !c.is_empty()).map(str::to_string))
+        .unwrap_or_else(|| (if cfg!(target_os = "macos") { "open" } else { "xdg-open" }).to_string())
+}
+
+pub fn terminal_browser() -> bool {
+    let browser = browser();
+    let program = browser.split_whitespace().next().unwrap_or_default();
+    let name = program.rsplit('/').next().unwrap_or(program);
+    TERMINAL_BROWSERS.contains(&name)
+}
+
+pub fn open_url(url: &str) -> bool {
+    let Some(mut command) = command(&browser(), Some(url)) else { return false; };
+    let Ok(mut child) = command.stdin(Stdio::null()).stdout(Stdio::null()).stderr(Stdio::null()).spawn() else { return false; };
+    std::thread::spawn(move || child.wait());
+    true
+}
+
+pub fn browse(url: &str) -> io::Result<()> {
+    let browser = browser();
+    let status = command(&browser, Some(url)).ok_or_else(|| io::Error::other("empty $BROWSER"))?.status()?;
+    match status.success() {
+        true => Ok(()),
+        false => Err(io::Error::other(format!("{} exited with {}", browser, status))),
+    }
+}
+
+pub fn page(text: &str) -> io::Result<()> {
+    let pager = from_env(&["PAGER"], "less");
+    let mut child = command(&pager, None).ok_or_else(|| io::Error::other("empty $PAGER"))?
+        .stdin(Stdio::piped())
+        .spawn()?;
+    if let Some(mut stdin) = child.stdin.take() {
+        match stdin.write_all(text.as_bytes()) {
+            Err(e) if e.kind() != io::ErrorKind::BrokenPipe => return Err(e),
+            _ => {}
+        }
+    }
+    child.wait()?;
+    Ok(())
+}
+
+pub fn edit(text: &str, name: &str) -> io::Result<()> {
+    let editor = from_env(&["VISUAL", "EDITOR"], "vi");
+    let path = write_temp(name, text)?;
+    let status = command(&editor, path.to_str())
+        .ok_or_else(|| io::Error::other("empty $EDITOR"))
+        .and_then(|mut c| c.status());
+    let _ = std::fs::remove_file(&path);
+    match status? {
+        s if s.success() => Ok(()),
+        s => Err(io::Error::other(format!("{} exited with {}", editor, s))),
+    }
+}
+
+fn write_temp(name: &str, text: &str) -> io::Result<PathBuf> {
+    write_temp_in(&env::temp_dir(), name, text, || SystemTime::now().duration_since(UNIX_EPOCH).map_or(0, |d| d.subsec_nanos()))
+}
+
+fn write_temp_in(dir: &Path, name: &str, text: &str, nanos: impl Fn() -> u32) -> io::Result<PathBuf> {
+    let name: String = name.chars().map(|c| if c.is_alphanumeric() || c == '-' { c } else { '_' }).collect();
+    let mut options = OpenOptions::new();
+    options.write(true).create_new(true);
+    #[cfg(unix)]
+    options.mode(0o600);
+    let mut attempt = 0;
+    loop {
+        let path = dir.join(format!("rwiki-{}-{:08x}{}-{}.txt", std::process::id(), nanos(), attempt, name));
+        match options.open(&path) {
+            Ok(mut file) => {
+                if let Err(e) = file.write_all(text.as_bytes()) {
+                    let _ = std::fs::remove_file(&path);
+                    return Err(e);
+                }
+                return Ok(path);
+            }
+            Err(e) if e.kind() == io::ErrorKind::AlreadyExists && attempt < 16 => attempt += 1,
+            Err(e) => return Err(e),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parts(command: Command) -> Vec<String> {
+        std::iter::once(command.get_program()).chain(command.get_args()).map(|s| s.to_string_lossy().into_owned()).collect()
+    }
+
+    #[test]
+    fn commands_substitute_or_append_their_argument() {
+        let url = "https://en.wikipedia.org/wiki/Rust";
+        assert_eq!(parts(command("firefox --new-tab", Some(url)).unwrap()), ["firefox", "--new-tab", url]);
+        assert_eq!(parts(command("w3m -o url=%s -M", Some(url)).unwrap()), ["w3m", "-o", &format!("url={}", url), "-M"]);
+        assert_eq!(parts(command("  less  -R ", None).unwrap()), ["less", "-R"]);
+        assert!(command("", Some(url)).is_none());
+        assert!(command("   ", None).is_none());
+    }
+
+    #[test]
+    fn temp_files_are_sanitized_private_and_never_reused() {
+        let dir = env::temp_dir().join(format!("rwiki-external-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let taken = dir.join(format!("rwiki-{}-{:08x}0-AC_DC_live.txt", std::process::id(), 7));
+        std::fs::write(&taken, "old").unwrap();
+        let path = write_temp_in(&dir, "AC/DC live", "text", || 7).unwrap();
+        assert_eq!(path, dir.join(format!("rwiki-{}-{:08x}1-AC_DC_live.txt", std::process::id(), 7)));
+        assert_eq!((std::fs::read_to_string(&taken).unwrap(), std::fs::read_to_string(&path).unwrap()), ("old".to_string(), "text".to_string()));
+        #[cfg(unix)]
+        assert_eq!(std::os::unix::fs::PermissionsExt::mode(&std::fs::metadata(&path).unwrap().permissions()) & 0o777, 0o600);
+        for attempt in 2..=16 {
+            std::fs::write(dir.join(format!("rwiki-{}-{:08x}{}-AC_DC_live.txt", std::process::id(), 7, attempt)), "").unwrap();
+        }
+        let error = write_temp_in(&dir, "AC/DC live", "text", || 7).unwrap_err();
+        std::fs::remove_dir_all(&dir).unwrap();
+        assert_eq!(error.kind(), io::ErrorKind::AlreadyExists);
+    }
+}
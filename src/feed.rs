@@ -0,0 +1,162 @@
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use anyhow::{anyhow, Result};
+use serde_json::Value;
+
+use crate::{civil_date, site::Site, table};
+
+const MAX_AGE: Duration = Duration::from_secs(60 * 60);
+const MOST_READ_LIMIT: usize = 10;
+
+#[derive(Clone, Debug)]
+pub struct Item {
+    pub title: String,
+    pub text: String,
+    pub detail: String,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Kind {
+    Featured,
+    News,
+    OnThisDay,
+    MostRead,
+}
+
+impl Kind {
+    pub fn name(self) -> &'static str {
+        match self {
+            Kind::Featured => "Featured article",
+            Kind::News => "In the news",
+            Kind::OnThisDay => "On this day",
+            Kind::MostRead => "Most read",
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct Section {
+    pub kind: Kind,
+    pub items: Vec<Item>,
+}
+
+fn page_title(page: &Value) -> Option<String> {
+    page.pointer("/titles/normalized")
+        .or_else(|| page.get("normalizedtitle"))
+        .and_then(|v| v.as_str())
+        .map(str::to_string)
+        .or_else(|| page.get("title")?.as_str().map(|t| t.replace('_', " ")))
+}
+
+fn text(value: &Value, key: &str) -> String {
+    value.get(key).and_then(|v| v.as_str()).unwrap_or("").to_string()
+}
+
+fn parse(json: &Value) -> Vec<Section> {
+    let list = |key: &str| json.get(key).and_then(|v| v.as_array()).cloned().unwrap_or_default();
+    let mut sections = Vec::new();
+
+    if let Some(page) = json.get("tfa")
+        && let Some(title) = page_title(page)
+    {
+        sections.push(Section {
+            kind: Kind::Featured,
+            items: vec![Item { title, text: text(page, "description"), detail: text(page, "extract") }],
+        });
+    }
+
+    let news: Vec<Item> = list("news").iter().filter_map(|story| {
+        let title = story.get("links")?.as_array()?.iter().find_map(page_title)?;
+        Some(Item { title, text: table::plain_text(&text(story, "story")), detail: String::new() })
+    }).collect();
+    if !news.is_empty() { sections.push(Section { kind: Kind::News, items: news }); }
+
+    let events: Vec<Item> = list("onthisday").iter().filter_map(|event| {
+        let title = event.get("pages")?.as_array()?.iter().find_map(page_title)?;
+        let year = event.get("year").and_then(|y| y.as_i64()).map(|y| format!("{}: ", y)).unwrap_or_default();
+        Some(Item { title, text: format!("{}{}", year, text(event, "text")), detail: String::new() })
+    }).collect();
+    if !events.is_empty() { sections.push(Section { kind: Kind::OnThisDay, items: events }); }
+
+    let most_read: Vec<Item> = json.pointer("/mostread/articles").and_then(|v| v.as_array()).into_iter().flatten()
+        .filter_map(|page| {
+            let views = page.get("views").and_then(|v| v.as_u64()).map(|v| format!("{} views", v)).unwrap_or_default();
+            Some(Item { title: page_title(page)?, text: text(page, "description"), detail: views })
+        })
+        .take(MOST_READ_LIMIT)
+        .collect();
+    if !most_read.is_empty() { sections.push(Section { kind: Kind::MostRead, items: most_read }); }
+
+    sections
+}
+
+pub async fn fetch(client: &reqwest::Client, site: &Site) -> Result<Vec<Section>> {
+    let secs = SystemTime::now().duration_since(UNIX_EPOCH)?.as_secs() as i64;
+    let (year, month, day) = civil_date(secs.div_euclid(86400));
+    let date = format!("{:04}-{:02}-{:02}", year, month, day);
+
+    let cached = site.cached_feed(&date).await;
+    let fresh = cached.as_ref().is_some_and(|(_, modified)| modified.elapsed().is_ok_and(|age| age < MAX_AGE));
+    let json = match cached {
+        Some((json, _)) if fresh || site.offline => json,
+        None if site.offline => return Err(anyhow!("the feed for {} is not available offline", date)),
+        cached => {
+            let url = format!("{}/feed/featured/{:04}/{:02}/{:02}", site.rest_url(), year, month, day);
+            let response = async { client.get(url).send().await?.error_for_status()?.json::<Value>().await }.await;
+            match (response, cached) {
+                (Ok(json), _) => {
+                    site.store_feed(&date, &json).await;
+                    json
+                }
+                (Err(_), Some((json, _))) => json,
+                (Err(e), None) => return Err(e.into()),
+            }
+        }
+    };
+
+    let sections = parse(&json);
+    if sections.is_empty() { return Err(anyhow!("the feed for {} is empty", date)); }
+    Ok(sections)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn the_feed_is_parsed_into_its_sections() {
+        let most_read: Vec<Value> = (1..=12).map(|i| serde_json::json!({ "title": format!("Page_{}", i), "description": "Popular", "views": 1000 - i })).collect();
+        let json = serde_json::json!({
+            "tfa": { "titles": { "normalized": "Featured Page" }, "description": "A page", "extract": "It is featured." },
+            "news": [
+                { "story": "<b>Something</b> <a href=\"./Event\">happened</a>.", "links": [{ "title": "Event_name" }] },
+                { "story": "No links", "links": [] },
+            ],
+            "onthisday": [
+                { "text": "A treaty was signed.", "year": 1648, "pages": [{ "normalizedtitle": "Peace of Westphalia" }] },
+                { "text": "Undated.", "pages": [{ "title": "Undated_event" }] },
+            ],
+            "mostread": { "articles": most_read },
+        });
+        let sections = parse(&json);
+        assert_eq!(sections.iter().map(|s| s.kind.name()).collect::<Vec<_>>(), ["Featured article", "In the news", "On this day", "Most read"]);
+        let items = |kind: Kind| -> Vec<(String, String, String)> {
+            sections.iter().find(|s| s.kind == kind).unwrap().items.iter().map(|i| (i.title.clone(), i.text.clone(), i.detail.clone())).collect()
+        };
+        let item = |title: &str, text: &str, detail: &str| (title.to_string(), text.to_string(), detail.to_string());
+        assert_eq!(items(Kind::Featured), [item("Featured Page", "A page", "It is featured.")]);
+        assert_eq!(items(Kind::News), [item("Event name", "Something happened.", "")]);
+        assert_eq!(items(Kind::OnThisDay), [item("Peace of Westphalia", "1648: A treaty was signed.", ""), item("Undated event", "Undated.", "")]);
+        let read = items(Kind::MostRead);
+        assert_eq!(read.len(), MOST_READ_LIMIT);
+        assert_eq!(read[0], item("Page 1", "Popular", "999 views"));
+    }
+
+    #[test]
+    fn empty_sections_are_skipped() {
+        let json = serde_json::json!({ "news": [], "onthisday": [{ "text": "No pages", "pages": [] }], "mostread": { "articles": [] } });
+        assert!(parse(&json).is_empty());
+        let sections = parse(&serde_json::json!({ "mostread": { "articles": [{ "title": "Only" }] } }));
+        assert_eq!(sections.iter().map(|s| s.kind).collect::<Vec<_>>(), [Kind::MostRead]);
+    }
+}
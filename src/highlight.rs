@@ -0,0 +1,131 @@
+use std::sync::OnceLock;
+
+use ratatui::prelude::*;
+use syntect::{easy::HighlightLines, highlighting::{FontStyle, Theme, ThemeSet}, parsing::SyntaxSet};
+use unicode_width::UnicodeWidthStr;
+
+use crate::{attr, decode_entities};
+
+const THEME: &str = "base16-ocean.dark";
+
+#[derive(Clone, Debug)]
+pub struct CodeBlock {
+    pub lang: Option<String>,
+    pub source: String,
+    pub lines: Vec<Vec<(String, Style)>>,
+    pub width: usize,
+}
+
+fn syntaxes() -> &'static (SyntaxSet, Theme) {
+    static ASSETS: OnceLock<(SyntaxSet, Theme)> = OnceLock::new();
+    ASSETS.get_or_init(|| {
+        let mut themes = ThemeSet::load_defaults();
+        (SyntaxSet::load_defaults_newlines(), themes.themes.remove(THEME).unwrap_or_default())
+    })
+}
+
+fn strip_tags(html: &str) -> String {
+    let mut out = String::with_capacity(html.len());
+    let mut in_tag = false;
+    for c in html.chars() {
+        match c {
+            '<' => in_tag = true,
+            '>' if in_tag => in_tag = false,
+            c if !in_tag => out.push(c),
+            _ => {}
+        }
+    }
+    decode_entities(&out)
+}
+
+fn convert(style: syntect::highlighting::Style) -> Style {
+    let fg = style.foreground;
+    let mut out = Style::default().fg(Color::Rgb(fg.r, fg.g, fg.b));
+    if style.font_style.contains(FontStyle::BOLD) { out = out.add_modifier(Modifier::BOLD); }
+    if style.font_style.contains(FontStyle::ITALIC) { out = out.add_modifier(Modifier::ITALIC); }
+    out
+}
+
+fn highlight(source: &str, lang: Option<&str>) -> Vec<Vec<(String, Style)>> {
+    let (syntax_set, theme) = syntaxes();
+    let syntax = lang
+        .and_then(|l| syntax_set.find_syntax_by_token(l))
+        .unwrap_or_else(|| syntax_set.find_syntax_plain_text());
+    let mut highlighter = HighlightLines::new(syntax, theme);
+
+    source.lines().map(|line| {
+        let line = format!("{}\n", line);
+        match highlighter.highlight_line(&line, syntax_set) {
+            Ok(ranges) => ranges.into_iter()
+                .map(|(style, text)| (text.trim_end_matches('\n').to_string(), convert(style)))
+                .filter(|(text, _)| !text.is_empty())
+                .collect(),
+            Err(_) => vec![(line.trim_end().to_string(), Style::default())],
+        }
+    }).collect()
+}
+
+pub fn parse_code_block(tag_content: &str, inner: &str) -> Option<CodeBlock> {
+    let lang = attr(tag_content, "class")
+        .and_then(|c| c.split_whitespace().find_map(|c| c.strip_prefix("mw-highlight-lang-")))
+        .filter(|l| *l != "text")
+        .map(str::to_string);
+    let source = strip_tags(inner).replace('\t', "    ");
+    let source = source.trim_matches('\n').trim_end().to_string();
+    if source.is_empty() { return None; }
+
+    let border = Style::default().fg(Color::DarkGray);
+    let code = highlight(&source, lang.as_deref());
+    let inner_width = code.iter()
+        .map(|l| l.iter().map(|(t, _)| t.width()).sum::<usize>())
+        .max().unwrap_or(0)
+        .max(lang.as_deref().map_or(0, |l| l.width() + 2));
+    let width = inner_width + 4;
+
+    let mut lines = Vec::with_capacity(code.len() + 2);
+    let label = lang.as_deref().map(|l| format!(" {} ", l)).unwrap_or_default();
+    lines.push(vec![(format!("┌─{}{}┐", label, "─".repeat(inner_width + 1 - label.width())), border)]);
+    for mut spans in code {
+        let used: usize = spans.iter().map(|(t, _)| t.width()).sum();
+        spans.insert(0, ("│ ".to_string(), border));
+        spans.push((" ".repeat(inner_width - used), Style::default()));
+        spans.push((" │".to_string(), border));
+        lines.push(spans);
+    }
+    lines.push(vec![(format!("└{}┘", "─".repeat(inner_width + 2)), border)]);
+
+    Some(CodeBlock { lang, source, lines, width })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn code_text(block: &CodeBlock) -> Vec<String> {
+        block.lines[1..block.lines.len() - 1].iter()
+            .map(|spans| spans[1..spans.len() - 2].iter().map(|(text, _)| text.as_str()).collect())
+            .collect()
+    }
+
+    #[test]
+    fn syntaxhighlight_keeps_its_language_and_whitespace() {
+        let inner = "<pre><span class=\"k\">fn</span> main() {\n\tlet x = 1 &lt; 2;\n}\n</pre>";
+        let block = parse_code_block("<div class=\"mw-highlight mw-highlight-lang-rust mw-content-ltr\" dir=\"ltr\"", inner).unwrap();
+        assert_eq!(block.lang.as_deref(), Some("rust"));
+        assert_eq!(block.source, "fn main() {\n    let x = 1 < 2;\n}");
+        assert_eq!(code_text(&block), ["fn main() {", "    let x = 1 < 2;", "}"]);
+        assert!(block.lines[0][0].0.starts_with("┌─ rust ─"));
+        assert!(block.lines[1].len() > 4);
+    }
+
+    #[test]
+    fn unknown_languages_and_plain_pre_fall_back_to_plain_text() {
+        let unknown = parse_code_block("<div class=\"mw-highlight mw-highlight-lang-nosuchlang\"", "<pre>a  b\n  c</pre>").unwrap();
+        assert_eq!(unknown.lang.as_deref(), Some("nosuchlang"));
+        assert_eq!(code_text(&unknown), ["a  b", "  c"]);
+        assert!(unknown.lines[1..3].iter().all(|spans| spans.len() == 4));
+        let plain = parse_code_block("<pre", "x = 1\n").unwrap();
+        assert_eq!((plain.lang, plain.source.as_str()), (None, "x = 1"));
+        assert!(parse_code_block("<pre", "\n\n").is_none());
+    }
+}
@@ -0,0 +1,119 @@
+use std::collections::{HashMap, VecDeque};
+
+use image::DynamicImage;
+
+pub const CACHE_LIMIT_BYTES: usize = 64 * 1024 * 1024;
+pub const MAX_CONCURRENT_DOWNLOADS: usize = 4;
+const SVG_MIN_WIDTH: u32 = 320;
+
+fn image_bytes(img: &DynamicImage) -> usize {
+    img.width() as usize * img.height() as usize * img.color().bytes_per_pixel() as usize
+}
+
+pub struct ImageCache {
+    entries: HashMap<String, DynamicImage>,
+    order: VecDeque<String>,
+    bytes: usize,
+    limit: usize,
+}
+
+impl ImageCache {
+    pub fn new(limit: usize) -> Self {
+        Self { entries: HashMap::new(), order: VecDeque::new(), bytes: 0, limit }
+    }
+
+    pub fn get(&mut self, url: &str) -> Option<DynamicImage> {
+        let img = self.entries.get(url)?.clone();
+        self.order.retain(|u| u != url);
+        self.order.push_back(url.to_string());
+        Some(img)
+    }
+
+    pub fn insert(&mut self, url: String, img: DynamicImage) {
+        let size = image_bytes(&img);
+        if size > self.limit { return; }
+        if let Some(old) = self.entries.remove(&url) {
+            self.bytes -= image_bytes(&old);
+            self.order.retain(|u| *u != url);
+        }
+        while self.bytes + size > self.limit {
+            let Some(oldest) = self.order.pop_front() else { break; };
+            if let Some(evicted) = self.entries.remove(&oldest) {
+                self.bytes -= image_bytes(&evicted);
+            }
+        }
+        self.bytes += size;
+        self.order.push_back(url.clone());
+        self.entries.insert(url, img);
+    }
+}
+
+pub fn thumbnail_url(url: &str, px: u32, file_width: u32) -> Option<String> {
+    let (host, path) = url.split_once("upload.wikimedia.org/")?;
+    let mut segments: Vec<&str> = path.split('/').collect();
+    let name = match segments.iter().position(|s| *s == "thumb") {
+        Some(i) => {
+            let name = *segments.get(i + 3)?;
+            segments.truncate(i + 4);
+            name
+        }
+        None => {
+            let name = *segments.last()?;
+            segments.insert(segments.len().checked_sub(3)?, "thumb");
+            name
+        }
+    };
+    let base = format!("{}upload.wikimedia.org/{}", host, segments.join("/"));
+
+    if name.to_ascii_lowercase().ends_with(".svg") {
+        return Some(format!("{}/{}px-{}.png", base, px.max(SVG_MIN_WIDTH), name));
+    }
+    if file_width > 0 && px >= file_width {
+        return Some(base.replacen("/thumb/", "/", 1));
+    }
+    let ext_ok = [".jpg", ".jpeg", ".png", ".gif", ".webp"].iter().any(|ext| name.to_ascii_lowercase().ends_with(ext));
+    (ext_ok && file_width > 0).then(|| format!("{}/{}px-{}", base, px, name))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const COMMONS: &str = "https://upload.wikimedia.org/wikipedia/commons";
+
+    #[test]
+    fn svg_thumbnails_are_rendered_at_least_at_the_minimum_width() {
+        let thumb = format!("{}/thumb/4/4a/Flag_of_Chile.svg/40px-Flag_of_Chile.svg.png", COMMONS);
+        assert_eq!(thumbnail_url(&thumb, 40, 0).unwrap(), format!("{}/thumb/4/4a/Flag_of_Chile.svg/{}px-Flag_of_Chile.svg.png", COMMONS, SVG_MIN_WIDTH));
+        let original = format!("{}/4/4a/Flag_of_Chile.svg", COMMONS);
+        assert_eq!(thumbnail_url(&original, 800, 0).unwrap(), format!("{}/thumb/4/4a/Flag_of_Chile.svg/800px-Flag_of_Chile.svg.png", COMMONS));
+    }
+
+    #[test]
+    fn commons_thumbnails_are_rewritten_to_the_requested_width() {
+        let thumb = format!("{}/thumb/a/ab/Berlin.jpg/220px-Berlin.jpg", COMMONS);
+        assert_eq!(thumbnail_url(&thumb, 640, 3000).unwrap(), format!("{}/thumb/a/ab/Berlin.jpg/640px-Berlin.jpg", COMMONS));
+        assert_eq!(thumbnail_url(&thumb, 640, 500).unwrap(), format!("{}/a/ab/Berlin.jpg", COMMONS));
+        assert_eq!(thumbnail_url(&thumb, 640, 0), None);
+        assert_eq!(thumbnail_url(&format!("{}/a/ab/Berlin.tiff", COMMONS), 640, 3000), None);
+        assert_eq!(thumbnail_url("https://example.org/a/ab/Berlin.jpg", 640, 3000), None);
+    }
+
+    fn image() -> DynamicImage {
+        DynamicImage::new_rgba8(10, 10)
+    }
+
+    #[test]
+    fn the_cache_evicts_the_least_recently_used_image() {
+        let mut cache = ImageCache::new(2 * image_bytes(&image()));
+        cache.insert("a".to_string(), image());
+        cache.insert("b".to_string(), image());
+        assert!(cache.get("a").is_some());
+        cache.insert("c".to_string(), image());
+        assert!(cache.get("b").is_none());
+        assert!(cache.get("a").is_some() && cache.get("c").is_some());
+        assert_eq!(cache.bytes, 2 * image_bytes(&image()));
+        cache.insert("huge".to_string(), DynamicImage::new_rgba8(20, 20));
+        assert!(cache.get("huge").is_none() && cache.get("a").is_some());
+    }
+}
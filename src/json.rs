@@ -0,0 +1,272 @@
+use anyhow::Result;
+use serde::Serialize;
+
+use crate::{site::Site, text::{InlineStyle, LineKind, TextLine}, ContentBlock, DisambiguationEntry, Reference};
+
+const SCHEMA_VERSION: u32 = 1;
+
+#[derive(Clone, Serialize)]
+struct Link {
+    text: String,
+    url: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    page: Option<String>,
+}
+
+#[derive(Serialize)]
+struct ListItem {
+    text: String,
+    depth: usize,
+    links: Vec<Link>,
+}
+
+#[derive(Clone, Serialize)]
+struct Image<'a> {
+    url: &'a str,
+    file: Option<&'a str>,
+    caption: Option<&'a str>,
+    width: u32,
+    height: u32,
+}
+
+#[derive(Serialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+enum Block<'a> {
+    Paragraph { text: String, links: Vec<Link> },
+    Quote { text: String, links: Vec<Link> },
+    List { ordered: bool, items: Vec<ListItem> },
+    Image(Image<'a>),
+    Table { caption: Option<&'a str>, rows: &'a [Vec<String>] },
+    Code { language: Option<&'a str>, source: &'a str },
+    Math { tex: &'a str, text: Option<&'a str> },
+}
+
+#[derive(Serialize)]
+struct Section<'a> {
+    title: String,
+    level: usize,
+    content: Vec<Block<'a>>,
+    sections: Vec<Section<'a>>,
+}
+
+#[derive(Serialize)]
+struct Field<'a> {
+    key: &'a str,
+    value: &'a str,
+}
+
+#[derive(Serialize)]
+struct Citation<'a> {
+    label: &'a str,
+    text: &'a str,
+    url: Option<&'a str>,
+}
+
+#[derive(Serialize)]
+struct Article<'a> {
+    version: u32,
+    kind: &'static str,
+    title: &'a str,
+    url: String,
+    infobox: Vec<Field<'a>>,
+    content: Vec<Block<'a>>,
+    sections: Vec<Section<'a>>,
+    images: Vec<Image<'a>>,
+    links: Vec<Link>,
+    references: Vec<Citation<'a>>,
+}
+
+#[derive(Serialize)]
+struct Entry<'a> {
+    title: &'a str,
+    description: &'a str,
+    url: String,
+}
+
+#[derive(Serialize)]
+struct Disambiguation<'a> {
+    version: u32,
+    kind: &'static str,
+    title: &'a str,
+    url: String,
+    entries: Vec<Entry<'a>>,
+}
+
+fn links(spans: &[(String, InlineStyle)], site: &Site, title: &str) -> Vec<Link> {
+    spans.iter().filter_map(|(text, style)| {
+        let href = style.link.as_deref()?;
+        Some(Link { text: text.trim().to_string(), url: site.link_url(title, href)?, page: site.page_title(href) })
+    }).collect()
+}
+
+fn image(figure: &crate::Figure) -> Image<'_> {
+    Image { url: &figure.url, file: figure.file.as_deref(), caption: figure.caption.as_deref(), width: figure.width, height: figure.height }
+}
+
+fn nest(flat: Vec<Section<'_>>) -> Vec<Section<'_>> {
+    let mut roots: Vec<Section> = Vec::new();
+    let mut stack: Vec<Section> = Vec::new();
+    for section in flat {
+        while let Some(done) = stack.pop_if(|open| open.level >= section.level) {
+            match stack.last_mut() {
+                Some(parent) => parent.sections.push(done),
+                None => roots.push(done),
+            }
+        }
+        stack.push(section);
+    }
+    while let Some(done) = stack.pop() {
+        match stack.last_mut() {
+            Some(parent) => parent.sections.push(done),
+            None => roots.push(done),
+        }
+    }
+    roots
+}
+
+fn push_line<'a>(content: &mut Vec<Block<'a>>, line: &TextLine, site: &Site, title: &str) {
+    let text = line.plain().trim().to_string();
+    if text.is_empty() { return; }
+    let line_links = links(&line.spans, site, title);
+    match &line.kind {
+        LineKind::ListItem { indent, marker } => {
+            let ordered = marker.starts_with(|c: char| c.is_ascii_digit());
+            let item = ListItem { text, depth: indent / 2, links: line_links };
+            match content.last_mut() {
+                Some(Block::List { items, .. }) if marker.is_empty() || item.depth > 0 => items.push(item),
+                Some(Block::List { ordered: open, items }) if *open == ordered => items.push(item),
+                _ => content.push(Block::List { ordered, items: vec![item] }),
+            }
+        }
+        LineKind::Quote(_) => content.push(Block::Quote { text, links: line_links }),
+        _ => content.push(Block::Paragraph { text, links: line_links }),
+    }
+}
+
+fn current<'s, 'a>(flat: &'s mut [Section<'a>], lead: &'s mut Vec<Block<'a>>) -> &'s mut Vec<Block<'a>> {
+    match flat.last_mut() {
+        Some(section) => &mut section.content,
+        None => lead,
+    }
+}
+
+fn collect<'a>(content: &[Block<'a>], sections: &[Section<'a>], images: &mut Vec<Image<'a>>, all_links: &mut Vec<Link>) {
+    for block in content {
+        let block_links = match block {
+            Block::Paragraph { links, .. } | Block::Quote { links, .. } => links.iter().collect(),
+            Block::List { items, .. } => items.iter().flat_map(|item| &item.links).collect(),
+            Block::Image(image) => { images.push(image.clone()); Vec::new() }
+            _ => Vec::new(),
+        };
+        for link in block_links {
+            if !all_links.iter().any(|l| l.url == link.url) { all_links.push(link.clone()); }
+        }
+    }
+    for section in sections {
+        collect(&section.content, &section.sections, images, all_links);
+    }
+}
+
+pub fn article_json(site: &Site, title: &str, infobox: &[(String, String)], blocks: &[ContentBlock], references: &[Reference], section: bool) -> Result<String> {
+    let mut lead = Vec::new();
+    let mut flat: Vec<Section> = Vec::new();
+
+    for block in blocks {
+        match block {
+            ContentBlock::Text(lines) => {
+                for line in lines {
+                    match line.kind {
+                        LineKind::Header(level) => flat.push(Section { title: line.plain().trim().to_string(), level, content: Vec::new(), sections: Vec::new() }),
+                        _ => push_line(current(&mut flat, &mut lead), line, site, title),
+                    }
+                }
+            }
+            ContentBlock::Image(figure) => current(&mut flat, &mut lead).push(Block::Image(image(figure))),
+            ContentBlock::Table(table) => current(&mut flat, &mut lead).push(Block::Table { caption: table.caption.as_deref(), rows: &table.grid }),
+            ContentBlock::Code(code) => current(&mut flat, &mut lead).push(Block::Code { language: code.lang.as_deref(), source: &code.source }),
+            ContentBlock::Math { tex, unicode } => current(&mut flat, &mut lead).push(Block::Math { tex, text: unicode.as_deref() }),
+        }
+    }
+
+    let sections = nest(flat);
+    let mut images = Vec::new();
+    let mut all_links = Vec::new();
+    collect(&lead, &sections, &mut images, &mut all_links);
+
+    let article = Article {
+        version: SCHEMA_VERSION,
+        kind: "article",
+        title,
+        url: site.page_url(title),
+        infobox: infobox.iter().filter(|_| !section).map(|(key, value)| Field { key, value }).collect(),
+        content: lead,
+        sections,
+        images,
+        links: all_links,
+        references: references.iter().filter(|_| !section).map(|r| Citation { label: &r.label, text: &r.text, url: r.url.as_deref() }).collect(),
+    };
+    Ok(serde_json::to_string_pretty(&article)?)
+}
+
+pub fn disambiguation_json(site: &Site, title: &str, entries: &[DisambiguationEntry]) -> Result<String> {
+    let page = Disambiguation {
+        version: SCHEMA_VERSION,
+        kind: "disambiguation",
+        title,
+        url: site.page_url(title),
+        entries: entries.iter().map(|e| Entry { title: &e.title, description: &e.description, url: site.page_url(&e.title) }).collect(),
+    };
+    Ok(serde_json::to_string_pretty(&page)?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use clap::Parser;
+    use crate::{cli::Args, text::rich_lines, Figure};
+
+    #[test]
+    fn article_json_has_a_stable_shape() {
+        let site = Site::from_args(&Args::parse_from(["rwiki"]));
+        let figure = Figure { url: "https://upload.example/a.png".to_string(), file: Some("A.png".to_string()), caption: Some("A map".to_string()), width: 200, height: 100, file_width: 400 };
+        let blocks = vec![
+            ContentBlock::Text(rich_lines("<p>Intro to <a href=\"/wiki/Foo_bar\">foo</a>.</p><h2>History</h2><p>Old.</p><h3>Early</h3><p>Older.</p>")),
+            ContentBlock::Image(figure),
+            ContentBlock::Text(rich_lines("<h2>Legacy</h2><ul><li>One</li></ul>")),
+        ];
+        let references = [Reference { label: "1".to_string(), text: "A source.".to_string(), url: Some("https://example.org".to_string()) }];
+        let json: serde_json::Value = serde_json::from_str(&article_json(&site, "Test", &[], &blocks, &references, false).unwrap()).unwrap();
+        let image = serde_json::json!({ "url": "https://upload.example/a.png", "file": "A.png", "caption": "A map", "width": 200, "height": 100 });
+        let link = serde_json::json!({ "text": "foo", "url": "https://en.wikipedia.org/wiki/Foo_bar", "page": "Foo bar" });
+        assert_eq!(json, serde_json::json!({
+            "version": SCHEMA_VERSION,
+            "kind": "article",
+            "title": "Test",
+            "url": "https://en.wikipedia.org/wiki/Test",
+            "infobox": [],
+            "content": [{ "type": "paragraph", "text": "Intro to foo.", "links": [link] }],
+            "sections": [
+                {
+                    "title": "History",
+                    "level": 2,
+                    "content": [{ "type": "paragraph", "text": "Old.", "links": [] }],
+                    "sections": [{
+                        "title": "Early",
+                        "level": 3,
+                        "content": [{ "type": "paragraph", "text": "Older.", "links": [] }, { "type": "image", "url": "https://upload.example/a.png", "file": "A.png", "caption": "A map", "width": 200, "height": 100 }],
+                        "sections": [],
+                    }],
+                },
+                {
+                    "title": "Legacy",
+                    "level": 2,
+                    "content": [{ "type": "list", "ordered": false, "items": [{ "text": "One", "depth": 0, "links": [] }] }],
+                    "sections": [],
+                },
+            ],
+            "images": [image],
+            "links": [link],
+            "references": [{ "label": "1", "text": "A source.", "url": "https://example.org" }],
+        }));
+    }
+}
@@ -1,46 +1,74 @@
-use anyhow::Result;
+use anyhow::{anyhow, Result};
 use crossterm::{
-    event::{self, DisableMouseCapture, EnableMouseCapture, Event, KeyCode, KeyEventKind, MouseEventKind},
+    event::{self, DisableMouseCapture, EnableMouseCapture, Event, KeyCode, KeyEventKind, KeyModifiers, MouseEventKind},
     execute,
     terminal::{disable_raw_mode, enable_raw_mode, EnterAlternateScreen, LeaveAlternateScreen},
 };
 use ratatui::{prelude::*, widgets::*};
-use ratatui_image::{picker::Picker, protocol::StatefulProtocol, StatefulImage};
+use html2text::render::text_renderer::TrivialDecorator;
+use serde::{Deserialize, Serialize};
+use ratatui_image::{picker::Picker, protocol::StatefulProtocol, thread::{ResizeResponse, ThreadProtocol}, StatefulImage};
 use image::DynamicImage;
-use std::{io, time::Duration, collections::HashMap};
-use tokio::sync::mpsc;
+use std::{io::{self, IsTerminal, Write}, time::Duration, collections::{HashMap, HashSet}, path::PathBuf, sync::{atomic::{AtomicBool, Ordering}, Arc, Mutex, OnceLock}};
+use tokio::sync::{mpsc, Semaphore};
+use unicode_width::UnicodeWidthStr;
+
+mod cli;
+mod clipboard;
+mod epub;
+mod external;
+mod feed;
+mod highlight;
+mod images;
+mod json;
+mod markdown;
+mod math;
+mod output;
+mod site;
+mod table;
+mod text;
+
+use clap::Parser;
+use highlight::CodeBlock;
+use site::Site;
+use table::Table;
+use text::{InlineStyle, LineKind, TextLine};
+
+static CONFIG_PATH: OnceLock<PathBuf> = OnceLock::new();
+static TUI_ACTIVE: AtomicBool = AtomicBool::new(false);
+
+fn config_value(key: &str) -> Option<String> {
+    let path = match CONFIG_PATH.get() {
+        Some(path) => path.clone(),
+        None => dirs::home_dir()?.join(".config/rmus/rmus.conf"),
+    };
+    let content = std::fs::read_to_string(path).ok()?;
+    content.lines().find_map(|line| {
+        let (name, val) = line.trim().split_once('=')?;
+        (name.trim() == key).then(|| val.trim().trim_matches('"').trim_matches('\'').to_string())
+    })
+}
 
 fn load_config_theme() -> Color {
     let default_theme = Color::Yellow;
-    let home = match dirs::home_dir() {
-        Some(path) => path,
-        None => return default_theme,
-    };
-    
-    let config_path = home.join(".config/rmus/rmus.conf");
-    
-    if let Ok(content) = std::fs::read_to_string(config_path) {
-        for line in content.lines() {
-            let line = line.trim();
-            if line.starts_with("theme_color") {
-                if let Some((_, val)) = line.split_once('=') {
-                    let val = val.trim().trim_matches('"').trim_matches('\'');
-                    if val.starts_with('#') && val.len() == 7 {
-                        let r = u8::from_str_radix(&val[1..3], 16);
-                        let g = u8::from_str_radix(&val[3..5], 16);
-                        let b = u8::from_str_radix(&val[5..7], 16);
-                        
-                        if let (Ok(r), Ok(g), Ok(b)) = (r, g, b) {
-                            return Color::Rgb(r, g, b);
-                        }
-                    }
-                }
-            }
+    if let Some(val) = config_value("theme_color")
+        && val.starts_with('#') && val.len() == 7
+    {
+        let r = u8::from_str_radix(&val[1..3], 16);
+        let g = u8::from_str_radix(&val[3..5], 16);
+        let b = u8::from_str_radix(&val[5..7], 16);
+
+        if let (Ok(r), Ok(g), Ok(b)) = (r, g, b) {
+            return Color::Rgb(r, g, b);
         }
     }
     default_theme
 }
 
+fn load_config_inline_images() -> bool {
+    config_value("inline_images").is_some_and(|v| v == "true")
+}
+
 fn preprocess_html(html: &str) -> (Option<String>, String) {
     let mut clean_html = html.to_string();
     let mut infobox_html = None;
@@ -50,70 +78,68 @@ fn preprocess_html(html: &str) -> (Option<String>, String) {
         let close_tag = format!("</{}>", tag);
         
         let mut search_pos = 0;
-        loop {
-            if let Some(pos) = clean_html[search_pos..].find(&open_tag) {
-                let actual_pos = search_pos + pos;
-                if let Some(tag_end) = clean_html[actual_pos..].find('>') {
-                    let tag_content = clean_html[actual_pos..actual_pos+tag_end].to_string();
-                    
-                    let mut depth = 1;
-                    let mut scan_pos = actual_pos + 1;
-                    let mut found_end = false;
+        while let Some(pos) = clean_html[search_pos..].find(&open_tag) {
+            let actual_pos = search_pos + pos;
+            if let Some(tag_end) = clean_html[actual_pos..].find('>') {
+                let tag_content = clean_html[actual_pos..actual_pos+tag_end].to_string();
+                
+                let mut depth = 1;
+                let mut scan_pos = actual_pos + 1;
+                let mut found_end = false;
+                
+                while depth > 0 {
+                    let next_open = clean_html[scan_pos..].find(&open_tag);
+                    let next_close = clean_html[scan_pos..].find(&close_tag);
                     
-                    while depth > 0 {
-                        let next_open = clean_html[scan_pos..].find(&open_tag);
-                        let next_close = clean_html[scan_pos..].find(&close_tag);
-                        
-                        match (next_open, next_close) {
-                            (Some(o), Some(c)) => {
-                                if o < c {
-                                    depth += 1;
-                                    scan_pos += o + 1;
-                                } else {
-                                    depth -= 1;
-                                    scan_pos += c + close_tag.len();
-                                    if depth == 0 {
-                                        found_end = true;
-                                        let end = scan_pos;
-                                        
-                                        let is_target = keywords.iter().any(|k| tag_content.contains(k));
-                                        
-                                        if is_target {
-                                            if tag == "table" && tag_content.contains("infobox") && infobox_html.is_none() {
-                                                infobox_html = Some(clean_html[actual_pos..end].to_string());
-                                            }
-                                            
-                                            clean_html.replace_range(actual_pos..end, "");
-                                            search_pos = actual_pos; 
-                                        } else {
-                                            search_pos = actual_pos + 1;
-                                        }
-                                    }
-                                }
-                            }
-                            (None, Some(c)) => {
+                    match (next_open, next_close) {
+                        (Some(o), Some(c)) => {
+                            if o < c {
+                                depth += 1;
+                                scan_pos += o + 1;
+                            } else {
                                 depth -= 1;
                                 scan_pos += c + close_tag.len();
                                 if depth == 0 {
+                                    found_end = true;
                                     let end = scan_pos;
+                                    
                                     let is_target = keywords.iter().any(|k| tag_content.contains(k));
+                                    
                                     if is_target {
                                         if tag == "table" && tag_content.contains("infobox") && infobox_html.is_none() {
                                             infobox_html = Some(clean_html[actual_pos..end].to_string());
                                         }
+                                        
                                         clean_html.replace_range(actual_pos..end, "");
-                                        search_pos = actual_pos;
+                                        search_pos = actual_pos; 
                                     } else {
                                         search_pos = actual_pos + 1;
                                     }
-                                    found_end = true;
                                 }
                             }
-                            _ => break,
                         }
+                        (None, Some(c)) => {
+                            depth -= 1;
+                            scan_pos += c + close_tag.len();
+                            if depth == 0 {
+                                let end = scan_pos;
+                                let is_target = keywords.iter().any(|k| tag_content.contains(k));
+                                if is_target {
+                                    if tag == "table" && tag_content.contains("infobox") && infobox_html.is_none() {
+                                        infobox_html = Some(clean_html[actual_pos..end].to_string());
+                                    }
+                                    clean_html.replace_range(actual_pos..end, "");
+                                    search_pos = actual_pos;
+                                } else {
+                                    search_pos = actual_pos + 1;
+                                }
+                                found_end = true;
+                            }
+                        }
+                        _ => break,
                     }
-                    if !found_end { break; } 
-                } else { break; }
+                }
+                if !found_end { break; } 
             } else { break; }
         }
     };
@@ -122,9 +148,112 @@ fn preprocess_html(html: &str) -> (Option<String>, String) {
 
     remove_elements("div", &["hatnote", "shortdescription", "toc", "siteSub", "mw-empty-elt"]);
 
+    remove_elements("span", &["mw-editsection"]);
+
     (infobox_html, clean_html)
 }
 
+fn element_end(html: &str, start: usize, tag: &str) -> Option<usize> {
+    let open_tag = format!("<{}", tag);
+    let close_tag = format!("</{}>", tag);
+    let is_open = |at: usize| html[at + open_tag.len()..].starts_with(|c: char| c.is_whitespace() || c == '>' || c == '/');
+    let mut depth = 0;
+    let mut pos = start;
+    loop {
+        let next_close = html[pos..].find(&close_tag).map(|c| pos + c)?;
+        let next_open = html[pos..next_close].match_indices(&open_tag).map(|(o, _)| pos + o).find(|&o| is_open(o));
+        match next_open {
+            Some(o) => {
+                depth += 1;
+                pos = o + open_tag.len();
+            }
+            None => {
+                depth -= 1;
+                pos = next_close + close_tag.len();
+                if depth == 0 { return Some(pos); }
+            }
+        }
+    }
+}
+
+fn superscript(label: &str) -> String {
+    label.chars().map(|c| math::superscript(c).unwrap_or(c)).collect()
+}
+
+const MARKER_SEPARATOR: char = '˒';
+
+fn extract_references(html: &str) -> (String, Vec<Reference>) {
+    let mut out = String::with_capacity(html.len());
+    let mut labels: Vec<(String, String)> = Vec::new();
+    let mut last_marker_end = None;
+    let mut pos = 0;
+
+    while let Some(found) = html[pos..].find("<sup") {
+        let start = pos + found;
+        let Some(tag_end) = html[start..].find('>').map(|e| start + e) else { break; };
+        let tag_content = &html[start..tag_end];
+        let Some(end) = element_end(html, start, "sup") else { break; };
+
+        out.push_str(&html[pos..start]);
+        if attr(tag_content, "class").is_some_and(|c| c.contains("reference")) {
+            let inner = &html[tag_end + 1..end];
+            let id = inner.find("href=\"#").and_then(|i| {
+                let rest = &inner[i + 7..];
+                rest.find('"').map(|q| rest[..q].to_string())
+            });
+            let text = html2text::from_read_with_decorator(inner.as_bytes(), 10000, TrivialDecorator::new());
+            let label = text.trim().trim_start_matches('[').trim_end_matches(']').to_string();
+            if let Some(id) = id && !label.is_empty() {
+                if last_marker_end == Some(out.len()) {
+                    out.push(MARKER_SEPARATOR);
+                }
+                let href = format!("{}{}", text::CITATION_PREFIX, label).replace('&', "&amp;").replace('"', "&quot;");
+                out.push_str(&format!("<a href=\"{}\">{}</a>", href, superscript(&label)));
+                last_marker_end = Some(out.len());
+                labels.push((id, label));
+            }
+        } else {
+            out.push_str(&html[start..end]);
+        }
+        pos = end;
+    }
+    out.push_str(&html[pos..]);
+
+    let mut references = Vec::new();
+    let mut clean_html = String::with_capacity(out.len());
+    let mut pos = 0;
+    while let Some(found) = out[pos..].find("<ol class=\"references\"") {
+        let start = pos + found;
+        let Some(end) = element_end(&out, start, "ol") else { break; };
+        clean_html.push_str(&out[pos..start]);
+
+        for item in out[start..end].split("<li").skip(1) {
+            let Some(tag_end) = item.find('>') else { continue; };
+            let Some(id) = attr(&item[..tag_end], "id") else { continue; };
+            let body = match item.find("class=\"reference-text\"") {
+                Some(i) => &item[i..],
+                None => item,
+            };
+            let body = body.split_once('>').map(|(_, rest)| rest).unwrap_or(body);
+            let text = html2text::from_read_with_decorator(format!("<p>{}</p>", body).as_bytes(), 10000, TrivialDecorator::new());
+            let text = text.split_whitespace().collect::<Vec<_>>().join(" ");
+            let url = body.split("<a ").skip(1).find_map(|a| {
+                let tag_content = &a[..a.find('>')?];
+                if !attr(tag_content, "class")?.contains("external") { return None; }
+                let href = decode_entities(attr(tag_content, "href")?);
+                Some(if href.starts_with("//") { format!("https:{}", href) } else { href })
+            });
+            let label = labels.iter().find(|(ref_id, _)| ref_id == id).map(|(_, l)| l.clone())
+                .unwrap_or_else(|| (references.len() + 1).to_string());
+            references.push(Reference { label, text, url });
+        }
+        pos = end;
+    }
+    clean_html.push_str(&out[pos..]);
+
+    (clean_html, references)
+}
+
 fn clean_infobox_text(raw: String) -> String {
     let mut output = String::new();
     let mut last_line_empty = false;
@@ -149,7 +278,7 @@ fn clean_infobox_text(raw: String) -> String {
                             if chars[i+1..j].iter().all(|c| c.is_numeric()) {
                                 is_citation = true;
                             }
-                            if &chars[i+1..j] == &['e','d','i','t'] { is_citation = true; }
+                            if chars[i+1..j] == ['e','d','i','t'] { is_citation = true; }
                             break; 
                         }
                         j += 1;
@@ -167,106 +296,61 @@ fn clean_infobox_text(raw: String) -> String {
     output.trim().to_string()
 }
 
-fn parse_content_blocks(html: &str) -> (Vec<ContentBlock>, Vec<String>, Vec<(usize, String, usize)>) {
+fn parse_content_blocks(html: &str) -> (Vec<ContentBlock>, Vec<Chapter>) {
     let mut blocks = Vec::new();
-    let mut image_urls = Vec::new();
     let mut chapters = Vec::new(); 
     let mut chapter_counter = 1;
 
+    let mut placeholders = Vec::new();
+    let html = extract_tables(html, &mut placeholders);
+    let html = extract_code(&html, &mut placeholders);
+    let html = extract_galleries(&html, &mut placeholders);
+    let html = extract_figures(&html, &mut placeholders);
+    let html = extract_math(&html, &mut placeholders);
     let parts: Vec<&str> = html.split("<img").collect();
     
-    let mut process_text = |text_html: &str, block_idx_offset: usize| {
-        let text = html2text::from_read(text_html.as_bytes(), 10000);
-        let mut clean_lines = Vec::new();
-        let mut found_lines = false;
+    let mut process_text = |text_html: &str, blocks: &mut Vec<ContentBlock>| {
+        let mut clean_lines: Vec<TextLine> = Vec::new();
 
-        for line in text.lines() {
-            let trimmed = line.trim();
-            
-            if trimmed.starts_with('[') && trimmed.contains("]:") { continue; } 
+        for line in text::rich_lines(text_html) {
+            let plain = line.plain();
+            let trimmed = plain.trim();
+
+            if let Some(idx) = trimmed.strip_prefix("###BLOCK###").and_then(|n| n.parse::<usize>().ok()) {
+                if !clean_lines.is_empty() {
+                    blocks.push(ContentBlock::Text(std::mem::take(&mut clean_lines)));
+                }
+                if let Some(block) = placeholders.get_mut(idx).and_then(|b| b.take()) {
+                    blocks.push(block);
+                }
+                continue;
+            }
+
+            if trimmed.is_empty() { continue; }
             
-            if trimmed.starts_with('*') && (trimmed.contains("Jump to search") || trimmed.contains("Jump to navigation")) { continue; }
+            if trimmed.contains("Jump to search") || trimmed.contains("Jump to navigation") { continue; }
             
-            if !trimmed.is_empty() && trimmed.chars().all(|c| c == '=' || c == '-') { continue; }
-
-            if trimmed.starts_with("* [") && trimmed.contains("][") { continue; }
+            if trimmed.chars().all(|c| c == '=' || c == '-') { continue; }
 
             if trimmed.contains("redirects here") && (trimmed.contains("For other uses") || trimmed.contains("disambiguation")) { continue; }
             if trimmed.starts_with("This article is part of a series") { continue; }
 
-            let mut is_header = false;
-            let mut display_text = trimmed.to_string();
-
-            if trimmed.starts_with('#') {
-                is_header = true;
-                display_text = trimmed.trim_start_matches('#').trim().to_string();
-            } else if trimmed.starts_with("==") && trimmed.ends_with("==") {
-                is_header = true;
-                display_text = trimmed.trim_matches('=').trim().to_string();
-            }
-
-            if is_header {
-                if !display_text.is_empty() && display_text != "Contents" {
-                    chapters.push((chapter_counter, display_text.clone(), block_idx_offset + 1)); 
-                    chapter_counter += 1;
-                    clean_lines.push(format!("###HEADER###{}", display_text));
-                    found_lines = true;
-                    continue;
-                }
-            }
-
-            if !trimmed.is_empty() {
-                let mut res = String::new();
-                let chars: Vec<char> = trimmed.chars().collect();
-                let mut i = 0;
-                while i < chars.len() {
-                    if chars[i] == '[' {
-                        let mut j = i + 1;
-                        let mut closed = false;
-                        while j < chars.len() {
-                            if chars[j] == ']' { closed = true; break; }
-                            j += 1;
-                        }
-                        
-                        if closed {
-                            let content = &chars[i+1..j];
-                            let content_str: String = content.iter().collect();
-                            
-                            if content_str.chars().all(|c| c.is_numeric()) || content_str == "edit" || content_str.chars().count() == 1 {
-                                i = j + 1;
-                                continue;
-                            }
-                            
-                            i += 1; 
-                            continue; 
-                        }
-                    }
-                    if chars[i] == ']' {
-                        i += 1;
-                        continue;
-                    }
-                    res.push(chars[i]);
-                    i += 1;
-                }
-                
-                let final_line = res.trim();
-                if !final_line.is_empty() {
-                    clean_lines.push(final_line.to_string());
-                    found_lines = true;
-                }
+            if let LineKind::Header(_) = line.kind {
+                if trimmed == "Contents" { continue; }
+                chapters.push((chapter_counter, trimmed.to_string(), blocks.len())); 
+                chapter_counter += 1;
             }
+
+            clean_lines.push(line);
         }
         
-        if found_lines {
-            return Some(clean_lines.join("\n"));
+        if !clean_lines.is_empty() {
+            blocks.push(ContentBlock::Text(clean_lines));
         }
-        None
     };
 
-    if !parts.is_empty() {
-        if let Some(t) = process_text(parts[0], blocks.len()) {
-            blocks.push(ContentBlock::Text(t));
-        }
+    if let Some(first) = parts.first() {
+        process_text(first, &mut blocks);
     }
 
     for part in parts.iter().skip(1) {
@@ -274,37 +358,284 @@ fn parse_content_blocks(html: &str) -> (Vec<ContentBlock>, Vec<String>, Vec<(usi
             let tag_content = &part[..tag_end];
             let remainder = &part[tag_end+1..];
             
-            let mut url = None;
-            if let Some(src_idx) = tag_content.find("src=\"") {
-                let s = &tag_content[src_idx + 5..];
-                if let Some(q_end) = s.find('"') {
-                    let u = &s[..q_end];
-                    if u.contains("upload.wikimedia.org") && !u.ends_with(".svg") {
-                        url = Some(if u.starts_with("//") { format!("https:{}", u) } else { u.to_string() });
-                    }
-                }
+            if let Some(figure) = figure_image(tag_content, None).filter(|figure| figure.width > 100) {
+                blocks.push(ContentBlock::Image(figure));
             }
-            
-            let is_big = if let Some(w_idx) = tag_content.find("width=\"") {
-                 let s = &tag_content[w_idx + 7..];
-                 let num_str: String = s.chars().take_while(|c| c.is_numeric()).collect();
-                 num_str.parse::<u32>().unwrap_or(0) > 100
-            } else { false };
 
-            if let Some(u) = url {
-                if is_big {
-                    image_urls.push(u.clone());
-                    blocks.push(ContentBlock::Image(u));
-                }
+            process_text(remainder, &mut blocks);
+        }
+    }
+    
+    (blocks, chapters)
+}
+
+fn figure_image(tag_content: &str, caption: Option<String>) -> Option<Figure> {
+    let src = attr(tag_content, "src")?;
+    let absolute = ["/", "http://", "https://"].iter().any(|prefix| src.starts_with(prefix));
+    if !absolute || src.contains("/media/math/") { return None; }
+    let url = if src.starts_with("//") { format!("https:{}", src) } else { src.to_string() };
+
+    let size = |name: &str| attr(tag_content, name).and_then(|v| v.trim().parse::<u32>().ok()).unwrap_or(0);
+    let (width, height) = (size("width"), size("height"));
+    let file_width = size("data-file-width");
+    let url = images::thumbnail_url(&url, width, 0).unwrap_or(url);
+    Some(Figure { file: file_name(&url), url, caption, width, height, file_width })
+}
+
+fn file_name(url: &str) -> Option<String> {
+    let segments: Vec<&str> = url.split('/').collect();
+    let name = match segments.iter().position(|s| *s == "thumb") {
+        Some(i) => segments.get(i + 3)?,
+        None => match segments.as_slice() {
+            [.., a, ab, name] if a.len() == 1 && ab.len() == 2 && ab.starts_with(*a) => name,
+            _ => return None,
+        },
+    };
+    Some(percent_decode(name).replace('_', " "))
+}
+
+fn civil_date(days: i64) -> (i64, u32, u32) {
+    let z = days + 719468;
+    let era = z.div_euclid(146097);
+    let doe = z.rem_euclid(146097);
+    let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = (doy - (153 * mp + 2) / 5 + 1) as u32;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 } as u32;
+    (yoe + era * 400 + i64::from(month <= 2), month, day)
+}
+
+fn percent_decode(text: &str) -> String {
+    unescape(text, b'%')
+}
+
+fn unescape(text: &str, marker: u8) -> String {
+    let bytes = text.as_bytes();
+    let mut out = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        let hex = (bytes[i] == marker).then(|| text.get(i + 1..i + 3)).flatten().and_then(|h| u8::from_str_radix(h, 16).ok());
+        match hex {
+            Some(b) => { out.push(b); i += 3; }
+            None => { out.push(bytes[i]); i += 1; }
+        }
+    }
+    String::from_utf8_lossy(&out).into_owned()
+}
+
+fn fragment_matches(fragment: &str, heading: &str) -> bool {
+    let heading = heading.trim().to_lowercase();
+    let decoded = percent_decode(fragment).replace('_', " ");
+    [unescape(&decoded, b'.'), decoded].iter().any(|anchor| anchor.trim().to_lowercase() == heading)
+}
+
+fn extract_galleries(html: &str, placeholders: &mut Vec<Option<ContentBlock>>) -> String {
+    let mut out = String::with_capacity(html.len());
+    let mut pos = 0;
+
+    while let Some(found) = html[pos..].find("<ul class=\"gallery") {
+        let start = pos + found;
+        let Some(end) = element_end(html, start, "ul") else { break; };
+        let gallery = &html[start..end];
+        out.push_str(&html[pos..start]);
+        pos = end;
+
+        let mut item_pos = 0;
+        while let Some(found) = gallery[item_pos..].find("<li class=\"gallerybox") {
+            let item_start = item_pos + found;
+            let Some(item_end) = element_end(gallery, item_start, "li") else { break; };
+            let item = &gallery[item_start..item_end];
+            item_pos = item_end;
+
+            let caption = item.find("<div class=\"gallerytext").and_then(|i| {
+                let open_end = i + item[i..].find('>')? + 1;
+                let close = element_end(item, i, "div")? - "</div>".len();
+                Some(table::plain_text(&item[open_end..close.max(open_end)]))
+            }).filter(|c| !c.is_empty());
+            let figure = item.find("<img")
+                .and_then(|i| item[i..].find('>').map(|e| &item[i + 4..i + e]))
+                .and_then(|tag_content| figure_image(tag_content, caption));
+            if let Some(figure) = figure {
+                out.push_str(&format!("<p>###BLOCK###{}</p>", placeholders.len()));
+                placeholders.push(Some(ContentBlock::Image(figure)));
+            }
+        }
+    }
+    out.push_str(&html[pos..]);
+    out
+}
+
+fn extract_figures(html: &str, placeholders: &mut Vec<Option<ContentBlock>>) -> String {
+    let mut out = String::with_capacity(html.len());
+    let mut pos = 0;
+
+    loop {
+        let figure = html[pos..].find("<figure").map(|i| (pos + i, "figure", "figcaption"));
+        let thumb = html[pos..].find("<div class=\"thumb ").map(|i| (pos + i, "div", "thumbcaption"));
+        let Some((start, tag, caption_class)) = [figure, thumb].into_iter().flatten().min_by_key(|(i, _, _)| *i) else { break; };
+        let Some(end) = element_end(html, start, tag) else { break; };
+        let inner = &html[start..end];
+
+        let caption_tag = if caption_class == "figcaption" { "figcaption" } else { "div" };
+        let caption = inner.find(&format!("<{}", caption_class))
+            .or_else(|| inner.find(&format!("class=\"{}\"", caption_class)).and_then(|i| inner[..i].rfind('<')))
+            .and_then(|i| {
+                let open_end = i + inner[i..].find('>')? + 1;
+                let close = element_end(inner, i, caption_tag)? - caption_tag.len() - 3;
+                Some(table::plain_text(&inner[open_end..close.max(open_end)]))
+            })
+            .filter(|c| !c.is_empty());
+        let image = inner.find("<img")
+            .and_then(|i| inner[i..].find('>').map(|e| &inner[i + 4..i + e]))
+            .and_then(|tag_content| figure_image(tag_content, caption))
+            .filter(|figure| figure.width > 100);
+
+        match image {
+            Some(figure) => {
+                out.push_str(&html[pos..start]);
+                out.push_str(&format!("<p>###BLOCK###{}</p>", placeholders.len()));
+                placeholders.push(Some(ContentBlock::Image(figure)));
+                pos = end;
             }
+            None => {
+                let next = start + 1;
+                out.push_str(&html[pos..next]);
+                pos = next;
+            }
+        }
+    }
+    out.push_str(&html[pos..]);
+    out
+}
+
+fn extract_tables(html: &str, placeholders: &mut Vec<Option<ContentBlock>>) -> String {
+    let mut out = String::with_capacity(html.len());
+    let mut pos = 0;
+
+    while let Some(found) = html[pos..].find("<table") {
+        let start = pos + found;
+        let Some(tag_end) = html[start..].find('>').map(|e| start + e) else { break; };
+        let is_wikitable = attr(&html[start..tag_end], "class").is_some_and(|c| c.split_whitespace().any(|c| c == "wikitable"));
+        let end = if is_wikitable { element_end(html, start, "table") } else { None };
 
-            if let Some(t) = process_text(remainder, blocks.len()) {
-                blocks.push(ContentBlock::Text(t));
+        match end.and_then(|end| Some((end, table::parse_table(&html[start..end])?))) {
+            Some((end, table)) => {
+                out.push_str(&html[pos..start]);
+                out.push_str(&format!("<p>###BLOCK###{}</p>", placeholders.len()));
+                placeholders.push(Some(ContentBlock::Table(table)));
+                pos = end;
+            }
+            None => {
+                out.push_str(&html[pos..tag_end]);
+                pos = tag_end;
             }
         }
     }
-    
-    (blocks, image_urls, chapters)
+    out.push_str(&html[pos..]);
+    out
+}
+
+fn extract_code(html: &str, placeholders: &mut Vec<Option<ContentBlock>>) -> String {
+    let mut out = String::with_capacity(html.len());
+    let mut pos = 0;
+
+    loop {
+        let highlight = html[pos..].find("<div class=\"mw-highlight").map(|i| (pos + i, "div"));
+        let pre = html[pos..].match_indices("<pre")
+            .map(|(i, _)| pos + i)
+            .find(|&i| html[i + 4..].starts_with(|c: char| c.is_whitespace() || c == '>'))
+            .map(|i| (i, "pre"));
+        let Some((start, tag)) = [highlight, pre].into_iter().flatten().min_by_key(|(i, _)| *i) else { break; };
+        let Some(tag_end) = html[start..].find('>').map(|e| start + e) else { break; };
+        let Some(end) = element_end(html, start, tag) else { break; };
+        let inner = &html[tag_end + 1..end - tag.len() - 3];
+
+        out.push_str(&html[pos..start]);
+        if let Some(code) = highlight::parse_code_block(&html[start..tag_end], inner) {
+            out.push_str(&format!("<p>###BLOCK###{}</p>", placeholders.len()));
+            placeholders.push(Some(ContentBlock::Code(code)));
+        }
+        pos = end;
+    }
+    out.push_str(&html[pos..]);
+    out
+}
+
+fn extract_math(html: &str, placeholders: &mut Vec<Option<ContentBlock>>) -> String {
+    let mut out = String::with_capacity(html.len());
+    let mut pos = 0;
+
+    while let Some(found) = html[pos..].find("<span class=\"mwe-math-element") {
+        let start = pos + found;
+        let Some(tag_end) = html[start..].find('>').map(|e| start + e) else { break; };
+        let Some(end) = element_end(html, start, "span") else { break; };
+        out.push_str(&html[pos..start]);
+        pos = end;
+
+        let Some(element) = math::parse_math_element(&html[start..tag_end], &html[tag_end + 1..end]) else { continue; };
+        let unicode = math::latex_to_unicode(&element.tex);
+        if element.display {
+            out.push_str(&format!("<p>###BLOCK###{}</p>", placeholders.len()));
+            placeholders.push(Some(ContentBlock::Math { tex: element.tex, unicode }));
+        } else {
+            let escape = |s: &str| s.replace('&', "&amp;").replace('<', "&lt;").replace('>', "&gt;");
+            match unicode {
+                Some(text) => out.push_str(&escape(&text)),
+                None => out.push_str(&format!("<code>{}</code>", escape(&element.tex))),
+            }
+        }
+    }
+    out.push_str(&html[pos..]);
+    out
+}
+
+fn attr<'a>(tag_content: &'a str, name: &str) -> Option<&'a str> {
+    let needle = format!("{}=\"", name);
+    let start = tag_content.match_indices(&needle)
+        .find(|(i, _)| *i == 0 || tag_content[..*i].ends_with(char::is_whitespace))
+        .map(|(i, _)| i + needle.len())?;
+    let len = tag_content[start..].find('"')?;
+    Some(&tag_content[start..start + len])
+}
+
+fn decode_entities(text: &str) -> String {
+    text.replace("&quot;", "\"")
+        .replace("&#39;", "'")
+        .replace("&lt;", "<")
+        .replace("&gt;", ">")
+        .replace("&nbsp;", " ")
+        .replace("&amp;", "&")
+}
+
+const NON_ARTICLE_NAMESPACES: &[&str] = &["File:", "Help:", "Wikipedia:", "Special:", "Category:", "Template:", "Portal:", "Talk:", "Wiktionary:"];
+
+fn parse_disambiguation(site: &Site, html: &str) -> Vec<DisambiguationEntry> {
+    let body = html.split("class=\"navbox").next().unwrap_or(html);
+    let body = body.split("id=\"See_also\"").next().unwrap_or(body);
+    let mut entries: Vec<DisambiguationEntry> = Vec::new();
+
+    for item in body.split("<li").skip(1) {
+        let item = item.split("</li>").next().unwrap_or(item);
+        let item = item.split("<ul").next().unwrap_or(item);
+
+        let Some(title) = item.split("<a ").skip(1).find_map(|a| {
+            let tag_content = &a[..a.find('>')?];
+            let href = attr(tag_content, "href")?;
+            if site.page_title(href).is_none() || tag_content.contains("class=\"new\"") { return None; }
+            let title = decode_entities(attr(tag_content, "title")?);
+            if NON_ARTICLE_NAMESPACES.iter().any(|ns| title.starts_with(ns)) { return None; }
+            Some(title)
+        }) else { continue; };
+
+        if entries.iter().any(|e| e.title == title) { continue; }
+
+        let inner = item.split_once('>').map(|(_, rest)| rest).unwrap_or("");
+        let text = html2text::from_read_with_decorator(format!("<p>{}</p>", inner).as_bytes(), 10000, TrivialDecorator::new());
+        let description = text.split_whitespace().collect::<Vec<_>>().join(" ");
+        entries.push(DisambiguationEntry { title, description });
+    }
+    entries
 }
 
 #[derive(Clone, Debug)]
@@ -313,10 +644,101 @@ struct SearchResult {
     snippet: String,
 }
 
+#[derive(Clone, Debug)]
+struct DisambiguationEntry {
+    title: String,
+    description: String,
+}
+
+#[derive(Clone, Debug)]
+struct Reference {
+    label: String,
+    text: String,
+    url: Option<String>,
+}
+
+#[derive(Clone, Debug)]
+struct PageSummary {
+    description: String,
+    extract: String,
+    thumbnail: Option<String>,
+}
+
+#[derive(Clone, Debug)]
+struct ImageInfo {
+    url: String,
+    page_url: String,
+    width: u64,
+    height: u64,
+    shown_width: u64,
+    description: String,
+    author: String,
+    license: String,
+}
+
+#[derive(Clone, Debug)]
+enum ImageInfoState {
+    Loading,
+    Ready(ImageInfo),
+    Failed,
+}
+
+#[derive(Clone, Debug)]
+enum SummaryState {
+    Loading,
+    Ready(PageSummary),
+    Failed,
+}
+
+enum FeedState {
+    Loading,
+    Ready(Vec<feed::Section>),
+    Failed,
+}
+
+type Chapter = (usize, String, usize);
+type Mark = (usize, usize, usize, usize);
+
+const HIGHLIGHT_CONTEXT: usize = 32;
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+struct Highlight {
+    quote: String,
+    before: String,
+    after: String,
+}
+type Selection = (VisualMode, (usize, usize), (usize, usize));
+
+enum External {
+    Pager(String),
+    Editor(String),
+    Browser(String),
+    Stop,
+}
+
+#[derive(Clone, Copy, PartialEq)]
+enum VisualMode {
+    Char,
+    Line,
+}
+
+#[derive(Clone, Debug)]
+struct Figure {
+    url: String,
+    file: Option<String>,
+    caption: Option<String>,
+    width: u32,
+    height: u32,
+    file_width: u32,
+}
+
 #[derive(Clone, Debug)]
 enum ContentBlock {
-    Text(String),
-    Image(String),
+    Text(Vec<TextLine>),
+    Image(Figure),
+    Table(Table),
+    Code(CodeBlock),
+    Math { tex: String, unicode: Option<String> },
 }
 
 #[derive(Clone, Debug)]
@@ -325,9 +747,13 @@ enum AppState {
     Searching, 
     Command,
     Chapters,
-    Loading,
     ResultsList,
+    Disambiguation,
     Reading,
+    Visual,
+    References,
+    TableView,
+    ImageView,
     Error(String),
 }
 
@@ -338,117 +764,375 @@ struct App {
     selected_index: usize,
     theme: Color,
     
+    summaries: HashMap<String, SummaryState>,
+    thumbnail_protocols: HashMap<String, ThreadProtocol>,
+    
     current_article_title: String,
+    redirected_from: Option<String>,
     current_article_info: String, 
+    disambiguation: Vec<DisambiguationEntry>,
+    disambiguation_state: ListState,
     content_blocks: Vec<ContentBlock>,
-    chapters: Vec<(usize, String, usize)>,
+    chapters: Vec<Chapter>,
+    references: Vec<Reference>,
     
+    layout: Option<ArticleLayout>,
+    pending_chapter: Option<usize>,
     scroll_offset: u16,
+    cursor: usize,
+    pending_key: Option<char>,
+    viewport_height: usize,
+    cursor_col: usize,
+    visual: Option<(VisualMode, (usize, usize))>,
+    highlights: Vec<Highlight>,
+    highlight_marks: Vec<Mark>,
+    note_quote: Option<String>,
+    external: Option<External>,
+    terminated: Option<&'static str>,
+    feed: FeedState,
+    home_index: usize,
+    home_list_state: ListState,
+    show_controls: bool,
+    h_scroll: usize,
+    viewport_blocks: Vec<usize>,
+    table_view: Option<usize>,
+    table_scroll: (u16, usize),
     chapter_list_state: ListState,
+    references_state: ListState,
     
     image_picker: Picker,
-    image_protocols: HashMap<String, StatefulProtocol>,
+    image_protocols: HashMap<String, ThreadProtocol>,
+    inline_images: bool,
+    image_view: usize,
+    image_info: HashMap<String, ImageInfoState>,
     
-    action_tx: mpsc::UnboundedSender<Action>,
+    generation: u64,
+    article_generation: u64,
+    results_generation: u64,
+    loading: Option<(String, std::time::Instant)>,
+    image_failures: HashSet<String>,
+    tick: usize,
+    notice: Option<String>,
+    site: Site,
+    action_tx: mpsc::UnboundedSender<(u64, Action)>,
+    event_tx: mpsc::UnboundedSender<(u64, NetworkEvent)>,
 }
 
 enum Action {
     Search(String),
     FetchArticle(String),
+    FetchRandom,
+    FetchFeed,
+    FetchSummary(String),
     DownloadImage(String),
+    FetchImageInfo(String),
+    Cancel(Vec<u64>),
+    DownloadThumbnail(String),
+    ExportEpub(PathBuf, epub::Article),
 }
 
 enum NetworkEvent {
     SearchResults(Vec<SearchResult>),
     ArticleLoaded {
         title: String,
+        redirected_from: Option<String>,
+        fragment: Option<String>,
         infobox: String,
+        infobox_fields: Vec<(String, String)>,
         blocks: Vec<ContentBlock>,
-        images: Vec<String>,
-        chapters: Vec<(usize, String, usize)>,
+        chapters: Vec<Chapter>,
+        references: Vec<Reference>,
+    },
+    DisambiguationLoaded {
+        title: String,
+        entries: Vec<DisambiguationEntry>,
     },
-    ArticleImageDownloaded(String, DynamicImage),
+    SummaryLoaded(String, Option<PageSummary>),
+    FeedLoaded(Option<Vec<feed::Section>>),
+    ArticleImageDownloaded(String, StatefulProtocol),
+    ArticleImageFailed(String),
+    ImageInfoLoaded(String, Option<ImageInfo>),
+    ThumbnailDownloaded(String, StatefulProtocol),
+    ImageResized { url: String, thumbnail: bool, response: ResizeResponse },
     ThemeUpdate(Color),
+    Signal(&'static str),
+    Copied(String, Option<&'static str>),
+    Notice(String),
     Error(String),
 }
 
-async fn run_network_loop(mut action_rx: mpsc::UnboundedReceiver<Action>, event_tx: mpsc::UnboundedSender<NetworkEvent>) {
-    let client = reqwest::Client::builder()
+async fn search(client: &reqwest::Client, site: &Site, query: &str) -> Result<Vec<SearchResult>> {
+    if site.offline {
+        let needle = query.to_lowercase();
+        return Ok(site.cached_titles().into_iter()
+            .filter(|title| title.to_lowercase().contains(&needle))
+            .map(|title| SearchResult { snippet: site.page_url(&title), title })
+            .collect());
+    }
+    let params = [("action", "opensearch"), ("search", query), ("limit", "10"), ("namespace", "0"), ("format", "json")];
+    let json = client.get(site.api_url()).query(&params).send().await?.json::<serde_json::Value>().await?;
+    let titles = json.get(1).and_then(|v| v.as_array()).ok_or_else(|| anyhow!("malformed search response"))?;
+    let urls = json.get(3).and_then(|v| v.as_array()).ok_or_else(|| anyhow!("malformed search response"))?;
+    Ok(titles.iter().zip(urls.iter()).map(|(t, u)| {
+        SearchResult { title: t.as_str().unwrap_or("").to_string(), snippet: u.as_str().unwrap_or("").to_string() }
+    }).collect())
+}
+
+async fn fetch_random(client: &reqwest::Client, site: &Site) -> Result<String> {
+    if site.offline {
+        let titles = site.cached_titles();
+        let seed = std::time::SystemTime::now().duration_since(std::time::UNIX_EPOCH)?.subsec_nanos() as usize;
+        return titles.get(seed % titles.len().max(1)).cloned().ok_or_else(|| anyhow!("no articles are available offline"));
+    }
+    let params = [("action", "query"), ("list", "random"), ("rnnamespace", "0"), ("rnlimit", "1"), ("format", "json")];
+    let json = client.get(site.api_url()).query(&params).send().await?.error_for_status()?.json::<serde_json::Value>().await?;
+    json.pointer("/query/random/0/title").and_then(|v| v.as_str()).map(|s| s.to_string()).ok_or_else(|| anyhow!("malformed random response"))
+}
+
+async fn fetch_article(client: &reqwest::Client, site: &Site, requested: String) -> Result<NetworkEvent> {
+    let (page, requested_fragment) = match requested.split_once('#') {
+        Some((page, fragment)) => (page.to_string(), Some(fragment.to_string())),
+        None => (requested, None),
+    };
+    let json = if site.offline {
+        site.cached_article(&page).await.ok_or_else(|| anyhow!("'{}' is not available offline", page))?
+    } else {
+        let params = [("action", "parse"), ("format", "json"), ("prop", "text|properties"), ("page", page.as_str()), ("redirects", "1")];
+        let json = client.get(site.api_url()).query(&params).send().await?.json::<serde_json::Value>().await?;
+        if let Some(info) = json.pointer("/error/info").and_then(|v| v.as_str()) {
+            return Err(anyhow!("{}", info));
+        }
+        let canonical = json.pointer("/parse/title").and_then(|v| v.as_str()).unwrap_or(&page);
+        site.store_article(&[&page, canonical], &json).await;
+        json
+    };
+    let html = json.pointer("/parse/text/*").and_then(|v| v.as_str()).ok_or_else(|| anyhow!("no article text for '{}'", page))?;
+
+    let title = json.pointer("/parse/title").and_then(|v| v.as_str()).map(|s| s.to_string()).unwrap_or(page);
+    let redirect = json.pointer("/parse/redirects/0");
+    let redirected_from = redirect.and_then(|r| r.get("from")).and_then(|v| v.as_str()).map(|s| s.to_string());
+    let fragment = requested_fragment.or_else(|| {
+        redirect.and_then(|r| r.get("tofragment")).and_then(|v| v.as_str()).map(|s| s.to_string())
+    });
+
+    let is_disambiguation = json.pointer("/parse/properties").and_then(|v| v.as_array())
+        .is_some_and(|props| props.iter().any(|p| p.get("name").and_then(|n| n.as_str()) == Some("disambiguation")));
+
+    let (html, references) = extract_references(html);
+    let (infobox_raw, clean_main_html) = preprocess_html(&html);
+
+    if is_disambiguation {
+        let entries = parse_disambiguation(site, &clean_main_html);
+        if !entries.is_empty() {
+            return Ok(NetworkEvent::DisambiguationLoaded { title, entries });
+        }
+    }
+    
+    let infobox_text = if let Some(ib) = &infobox_raw {
+        let t = html2text::from_read(ib.as_bytes(), 50);
+        clean_infobox_text(t)
+    } else { String::new() };
+    let infobox_fields = infobox_raw.as_deref().map(table::key_values).unwrap_or_default();
+
+    let (mut blocks, chapters) = parse_content_blocks(&clean_main_html);
+    for block in &mut blocks {
+        if let ContentBlock::Image(figure) = block
+            && figure.url.starts_with('/')
+        {
+            figure.url = format!("{}{}", site.base, figure.url);
+        }
+    }
+
+    Ok(NetworkEvent::ArticleLoaded {
+        title,
+        redirected_from,
+        fragment,
+        infobox: infobox_text,
+        infobox_fields,
+        blocks,
+        chapters,
+        references,
+    })
+}
+
+async fn fetch_summary(client: &reqwest::Client, site: &Site, title: &str) -> Result<PageSummary> {
+    if site.offline { return Err(anyhow!("summaries are not available offline")); }
+    let mut url = reqwest::Url::parse(&site.rest_url())?;
+    url.path_segments_mut()
+        .map_err(|_| anyhow!("invalid REST url"))?
+        .extend(["page", "summary", &title.replace(' ', "_")]);
+    let json = client.get(url).send().await?.error_for_status()?.json::<serde_json::Value>().await?;
+    let text = |ptr: &str| json.pointer(ptr).and_then(|v| v.as_str()).unwrap_or("").to_string();
+    let thumbnail = json.pointer("/thumbnail/source").and_then(|v| v.as_str()).map(|s| s.to_string());
+    Ok(PageSummary { description: text("/description"), extract: text("/extract"), thumbnail })
+}
+
+const FULL_IMAGE_MAX_WIDTH: u64 = 2048;
+
+async fn fetch_image_info(client: &reqwest::Client, site: &Site, file: &str) -> Result<ImageInfo> {
+    if site.offline { return Err(anyhow!("image information is not available offline")); }
+    let title = format!("File:{}", file);
+    let width = FULL_IMAGE_MAX_WIDTH.to_string();
+    let params = [
+        ("action", "query"), ("titles", title.as_str()), ("prop", "imageinfo"),
+        ("iiprop", "url|size|mime|extmetadata"), ("iiurlwidth", width.as_str()),
+        ("format", "json"), ("formatversion", "2"),
+    ];
+    let json = client.get(site.api_url()).query(&params).send().await?.error_for_status()?.json::<serde_json::Value>().await?;
+    let info = json.pointer("/query/pages/0/imageinfo/0").ok_or_else(|| anyhow!("no image info for {}", file))?;
+    let text = |ptr: &str| info.pointer(ptr).and_then(|v| v.as_str()).unwrap_or("").to_string();
+    let meta = |key: &str| table::plain_text(&text(&format!("/extmetadata/{}/value", key)));
+
+    let (width, height) = (info["width"].as_u64().unwrap_or(0), info["height"].as_u64().unwrap_or(0));
+    let raster = matches!(text("/mime").as_str(), "image/jpeg" | "image/png" | "image/gif" | "image/webp");
+    let original = raster && width <= FULL_IMAGE_MAX_WIDTH;
+    let url = if original { text("/url") } else { text("/thumburl") };
+    let shown_width = if original { width } else { info["thumbwidth"].as_u64().unwrap_or(FULL_IMAGE_MAX_WIDTH) };
+    if url.is_empty() { return Err(anyhow!("no image url for {}", file)); }
+
+    Ok(ImageInfo {
+        url,
+        page_url: text("/descriptionurl"),
+        width,
+        height,
+        shown_width,
+        description: meta("ImageDescription"),
+        author: meta("Artist"),
+        license: meta("LicenseShortName"),
+    })
+}
+
+async fn download_image(client: &reqwest::Client, site: &Site, url: &str, cache: &Mutex<images::ImageCache>, limit: &Semaphore) -> Result<DynamicImage> {
+    if let Some(img) = cache.lock().map_err(|_| anyhow!("image cache poisoned"))?.get(url) {
+        return Ok(img);
+    }
+    if site.offline { return Err(anyhow!("images are not available offline")); }
+    let _permit = limit.acquire().await?;
+    let bytes = client.get(url).send().await?.error_for_status()?.bytes().await?;
+    let flatten = url.ends_with(".svg.png");
+    let img = tokio::task::spawn_blocking(move || -> Result<DynamicImage> {
+        let img = image::load_from_memory(&bytes)?;
+        if !flatten { return Ok(img); }
+
+        let mut canvas = image::RgbaImage::from_pixel(img.width(), img.height(), image::Rgba([255, 255, 255, 255]));
+        image::imageops::overlay(&mut canvas, &img.to_rgba8(), 0, 0);
+        Ok(DynamicImage::ImageRgba8(canvas))
+    }).await??;
+    cache.lock().map_err(|_| anyhow!("image cache poisoned"))?.insert(url.to_string(), img.clone());
+    Ok(img)
+}
+
+async fn image_protocol(client: &reqwest::Client, site: &Site, url: &str, cache: &Mutex<images::ImageCache>, limit: &Semaphore, picker: Picker) -> Result<StatefulProtocol> {
+    let img = download_image(client, site, url, cache, limit).await?;
+    Ok(tokio::task::spawn_blocking(move || picker.new_resize_protocol(img)).await?)
+}
+
+fn http_client() -> reqwest::Client {
+    reqwest::Client::builder()
         .user_agent("WikiTui/0.1.0")
         .build()
-        .unwrap_or_else(|_| reqwest::Client::new());
+        .unwrap_or_else(|_| reqwest::Client::new())
+}
+
+async fn run_network_loop(site: Site, picker: Picker, mut action_rx: mpsc::UnboundedReceiver<(u64, Action)>, event_tx: mpsc::UnboundedSender<(u64, NetworkEvent)>) {
+    let client = http_client();
+    let cache = Arc::new(Mutex::new(images::ImageCache::new(images::CACHE_LIMIT_BYTES)));
+    let download_limit = Arc::new(Semaphore::new(images::MAX_CONCURRENT_DOWNLOADS));
+    let mut scoped_tasks: Vec<(u64, tokio::task::AbortHandle)> = Vec::new();
 
-    while let Some(action) = action_rx.recv().await {
+    while let Some((generation, action)) = action_rx.recv().await {
+        if let Action::Cancel(keep) = &action {
+            scoped_tasks.retain(|(generation, task)| keep.contains(generation) || { task.abort(); false });
+            continue;
+        }
         let client = client.clone();
+        let site = site.clone();
         let event_tx = event_tx.clone();
+        let cache = cache.clone();
+        let download_limit = download_limit.clone();
+        let picker = picker.clone();
 
-        tokio::spawn(async move {
-            match action {
-                Action::Search(query) => {
-                    let params = [("action", "opensearch"), ("search", query.as_str()), ("limit", "10"), ("namespace", "0"), ("format", "json")];
-                    if let Ok(resp) = client.get("https://en.wikipedia.org/w/api.php").query(&params).send().await {
-                        if let Ok(json) = resp.json::<serde_json::Value>().await {
-                            if let Some(array) = json.as_array() {
-                                if array.len() >= 4 {
-                                    let titles = array[1].as_array().unwrap();
-                                    let urls = array[3].as_array().unwrap(); 
-                                    let results: Vec<SearchResult> = titles.iter().zip(urls.iter()).map(|(t, u)| {
-                                        SearchResult { title: t.as_str().unwrap_or("").to_string(), snippet: u.as_str().unwrap_or("").to_string() }
-                                    }).collect();
-                                    let _ = event_tx.send(NetworkEvent::SearchResults(results));
-                                }
-                            }
-                        }
-                    }
+        let task = tokio::spawn(async move {
+            let event = match action {
+                Action::Search(query) => match search(&client, &site, &query).await {
+                    Ok(results) => NetworkEvent::SearchResults(results),
+                    Err(e) => NetworkEvent::Error(format!("Search failed: {}", e)),
+                },
+                Action::FetchArticle(title) => fetch_article(&client, &site, title).await
+                    .unwrap_or_else(|e| NetworkEvent::Error(format!("Could not load article: {}", e))),
+                Action::FetchRandom => match fetch_random(&client, &site).await {
+                    Ok(title) => fetch_article(&client, &site, title).await
+                        .unwrap_or_else(|e| NetworkEvent::Error(format!("Could not load article: {}", e))),
+                    Err(e) => NetworkEvent::Error(format!("Could not pick a random article: {}", e)),
+                },
+                Action::FetchFeed => NetworkEvent::FeedLoaded(feed::fetch(&client, &site).await.ok()),
+                Action::FetchSummary(title) => {
+                    let summary = fetch_summary(&client, &site, &title).await.ok();
+                    NetworkEvent::SummaryLoaded(title, summary)
                 }
-                Action::FetchArticle(title) => {
-                    let parse_client = client.clone();
-                    let parse_tx = event_tx.clone();
-                    let title_parse = title.clone();
-                    
-                    tokio::spawn(async move {
-                        let params = [("action", "parse"), ("format", "json"), ("prop", "text"), ("page", title_parse.as_str()), ("redirects", "1")];
-                        if let Ok(resp) = parse_client.get("https://en.wikipedia.org/w/api.php").query(&params).send().await {
-                            if let Ok(json) = resp.json::<serde_json::Value>().await {
-                                if let Some(html_val) = json.pointer("/parse/text/*") {
-                                    if let Some(html) = html_val.as_str() {
-                                        let (infobox_raw, clean_main_html) = preprocess_html(html);
-                                        
-                                        let infobox_text = if let Some(ib) = infobox_raw {
-                                            let t = html2text::from_read(ib.as_bytes(), 50);
-                                            clean_infobox_text(t)
-                                        } else { String::new() };
-
-                                        let (blocks, images, chapters) = parse_content_blocks(&clean_main_html);
-                                        
-                                        let _ = parse_tx.send(NetworkEvent::ArticleLoaded {
-                                            title: title_parse,
-                                            infobox: infobox_text,
-                                            blocks,
-                                            images,
-                                            chapters,
-                                        });
-                                    }
-                                }
-                            }
-                        }
-                    });
+                Action::DownloadImage(url) => match image_protocol(&client, &site, &url, &cache, &download_limit, picker).await {
+                    Ok(protocol) => NetworkEvent::ArticleImageDownloaded(url, protocol),
+                    Err(_) => NetworkEvent::ArticleImageFailed(url),
+                },
+                Action::Cancel(_) => return,
+                Action::FetchImageInfo(file) => {
+                    let info = fetch_image_info(&client, &site, &file).await.ok();
+                    NetworkEvent::ImageInfoLoaded(file, info)
                 }
-                Action::DownloadImage(url) => {
-                    if let Ok(resp) = client.get(&url).send().await {
-                        if let Ok(bytes) = resp.bytes().await {
-                            if let Ok(img) = image::load_from_memory(&bytes) {
-                                let _ = event_tx.send(NetworkEvent::ArticleImageDownloaded(url, img));
-                            }
-                        }
+                Action::DownloadThumbnail(url) => match image_protocol(&client, &site, &url, &cache, &download_limit, picker).await {
+                    Ok(protocol) => NetworkEvent::ThumbnailDownloaded(url, protocol),
+                    Err(_) => return,
+                },
+                Action::ExportEpub(path, article) => {
+                    let progress = |done, total| { let _ = event_tx.send((generation, NetworkEvent::Notice(format!("Exporting: image {}/{}", done, total)))); };
+                    match epub::write(&client, &site, article.title(), std::slice::from_ref(&article), &path, download_limit, progress).await {
+                        Ok(()) => NetworkEvent::Notice(format!("Exported to {}", path.display())),
+                        Err(e) => NetworkEvent::Notice(format!("Export failed: {}", e)),
                     }
                 }
-            }
+            };
+            let _ = event_tx.send((generation, event));
         });
+        if generation != 0 {
+            scoped_tasks.retain(|(_, task)| !task.is_finished());
+            scoped_tasks.push((generation, task.abort_handle()));
+        }
     }
 }
 
-async fn run_config_watcher(event_tx: mpsc::UnboundedSender<NetworkEvent>) {
+#[cfg(unix)]
+async fn run_signal_listener(event_tx: mpsc::UnboundedSender<(u64, NetworkEvent)>) {
+    use tokio::signal::unix::{signal, SignalKind};
+    let (Ok(mut term), Ok(mut hup), Ok(mut int), Ok(mut tstp)) = (
+        signal(SignalKind::terminate()),
+        signal(SignalKind::hangup()),
+        signal(SignalKind::interrupt()),
+        signal(SignalKind::from_raw(libc::SIGTSTP)),
+    ) else { return; };
+
+    loop {
+        let name = tokio::select! {
+            _ = term.recv() => "SIGTERM",
+            _ = hup.recv() => "SIGHUP",
+            _ = int.recv() => "SIGINT",
+            _ = tstp.recv() => "SIGTSTP",
+        };
+        if event_tx.send((0, NetworkEvent::Signal(name))).is_err() { break; }
+    }
+}
+
+fn restore_on_panic() {
+    let default_hook = std::panic::take_hook();
+    std::panic::set_hook(Box::new(move |info| {
+        if TUI_ACTIVE.swap(false, Ordering::SeqCst) {
+            let _ = disable_raw_mode();
+            let _ = execute!(io::stdout(), DisableMouseCapture, LeaveAlternateScreen, crossterm::cursor::Show);
+        }
+        default_hook(info);
+    }));
+}
+
+async fn run_config_watcher(event_tx: mpsc::UnboundedSender<(u64, NetworkEvent)>) {
     let mut last_color = load_config_theme();
     let mut interval = tokio::time::interval(Duration::from_secs(1));
 
@@ -457,82 +1141,372 @@ async fn run_config_watcher(event_tx: mpsc::UnboundedSender<NetworkEvent>) {
         let new_color = load_config_theme();
         if new_color != last_color {
             last_color = new_color;
-            let _ = event_tx.send(NetworkEvent::ThemeUpdate(new_color));
+            let _ = event_tx.send((0, NetworkEvent::ThemeUpdate(new_color)));
         }
     }
 }
 
 impl App {
-    fn new(action_tx: mpsc::UnboundedSender<Action>) -> Self {
-        let image_picker = Picker::from_query_stdio().unwrap_or_else(|_| Picker::from_fontsize((8, 12)));
+    fn new(site: Site, image_picker: Picker, action_tx: mpsc::UnboundedSender<(u64, Action)>, event_tx: mpsc::UnboundedSender<(u64, NetworkEvent)>) -> Self {
         Self {
             state: AppState::Home,
             input: String::new(),
             search_results: vec![],
             selected_index: 0,
             theme: load_config_theme(),
+            summaries: HashMap::new(),
+            thumbnail_protocols: HashMap::new(),
             current_article_title: String::new(),
+            redirected_from: None,
             current_article_info: String::new(),
+            disambiguation: Vec::new(),
+            disambiguation_state: ListState::default(),
             content_blocks: Vec::new(),
             chapters: Vec::new(),
+            references: Vec::new(),
+            layout: None,
+            pending_chapter: None,
             scroll_offset: 0,
+            cursor: 0,
+            pending_key: None,
+            viewport_height: 0,
+            cursor_col: 0,
+            visual: None,
+            highlights: Vec::new(),
+            highlight_marks: Vec::new(),
+            note_quote: None,
+            external: None,
+            terminated: None,
+            feed: FeedState::Loading,
+            home_index: 0,
+            home_list_state: ListState::default(),
+            show_controls: false,
+            h_scroll: 0,
+            viewport_blocks: Vec::new(),
+            table_view: None,
+            table_scroll: (0, 0),
             chapter_list_state: ListState::default(),
+            references_state: ListState::default(),
             image_picker,
             image_protocols: HashMap::new(),
+            inline_images: load_config_inline_images(),
+            image_view: 0,
+            image_info: HashMap::new(),
+            generation: 0,
+            article_generation: 0,
+            results_generation: 0,
+            loading: None,
+            image_failures: HashSet::new(),
+            tick: 0,
+            notice: None,
+            site,
             action_tx,
+            event_tx,
         }
     }
 
-    fn on_tick(&mut self, event: Option<NetworkEvent>) {
-        if let Some(network_event) = event {
-            match network_event {
-                NetworkEvent::SearchResults(results) => {
-                    self.search_results = results;
-                    self.selected_index = 0;
-                    self.state = AppState::ResultsList;
-                }
-                NetworkEvent::ArticleLoaded { title, infobox, blocks, images, chapters } => {
-                    self.current_article_title = title;
-                    self.current_article_info = infobox;
-                    self.content_blocks = blocks;
-                    self.chapters = chapters;
-                    self.image_protocols.clear();
-                    self.scroll_offset = 0;
-                    self.state = AppState::Reading;
-                    self.chapter_list_state.select(Some(0));
-                    
-                    for url in images {
-                        let _ = self.action_tx.send(Action::DownloadImage(url));
+    fn on_tick(&mut self, event: Option<(u64, NetworkEvent)>) {
+        let Some((generation, network_event)) = event else {
+            self.tick = self.tick.wrapping_add(1);
+            return;
+        };
+        let current = match &network_event {
+            NetworkEvent::ArticleImageDownloaded(..) | NetworkEvent::ArticleImageFailed(_) | NetworkEvent::ImageInfoLoaded(..) => self.article_generation,
+            NetworkEvent::SummaryLoaded(..) | NetworkEvent::ThumbnailDownloaded(..) => self.results_generation,
+            _ => self.generation,
+        };
+        if generation != 0 && generation != current { return; }
+        match network_event {
+            NetworkEvent::SearchResults(results) => {
+                self.results_generation = generation;
+                self.cancel_stale();
+                self.loading = None;
+                self.search_results = results;
+                self.selected_index = 0;
+                let titles: HashSet<&str> = self.search_results.iter().map(|r| r.title.as_str()).collect();
+                self.summaries.retain(|title, _| titles.contains(title.as_str()));
+                let thumbnails: HashSet<&str> = self.summaries.values().filter_map(|s| match s {
+                    SummaryState::Ready(summary) => summary.thumbnail.as_deref(),
+                    _ => None,
+                }).collect();
+                self.thumbnail_protocols.retain(|url, _| thumbnails.contains(url.as_str()));
+                self.state = AppState::ResultsList;
+                self.request_summary();
+            }
+            NetworkEvent::ArticleLoaded { title, redirected_from, fragment, infobox, blocks, chapters, references, .. } => {
+                self.article_generation = generation;
+                self.cancel_stale();
+                self.loading = None;
+                self.current_article_title = title;
+                self.redirected_from = redirected_from;
+                self.current_article_info = infobox;
+                self.content_blocks = blocks;
+                self.layout = None;
+                self.chapters = chapters;
+                self.references = references;
+                self.references_state.select(None);
+                self.image_protocols.clear();
+                self.image_failures.clear();
+                self.image_info.clear();
+                self.image_view = 0;
+                self.scroll_offset = 0;
+                self.cursor = 0;
+                self.visual = None;
+                self.highlights = self.site.highlights(&self.current_article_title);
+                self.highlight_marks.clear();
+                self.h_scroll = 0;
+                self.state = AppState::Reading;
+                self.chapter_list_state.select(Some(0));
+
+                if let Some(fragment) = fragment
+                    && let Some(i) = self.chapters.iter().position(|(_, name, _)| fragment_matches(&fragment, name))
+                {
+                    self.jump_to_chapter(i);
+                }
+                
+                let pane_cols = if self.inline_images { INLINE_IMAGE_MAX_COLS } else { SIDEBAR_WIDTH - 2 };
+                let pane_px = pane_cols as u32 * self.image_picker.font_size().0 as u32;
+                let mut urls = Vec::new();
+                for block in &mut self.content_blocks {
+                    if let ContentBlock::Image(figure) = block {
+                        if let Some(url) = images::thumbnail_url(&figure.url, pane_px, figure.file_width) {
+                            figure.url = url;
+                        }
+                        urls.push(figure.url.clone());
                     }
                 }
-                NetworkEvent::ArticleImageDownloaded(url, img) => {
-                    let protocol = self.image_picker.new_resize_protocol(img);
-                    self.image_protocols.insert(url, protocol);
+                for url in urls {
+                    self.send(Action::DownloadImage(url));
                 }
-                NetworkEvent::ThemeUpdate(new_color) => {
-                    self.theme = new_color;
+            }
+            NetworkEvent::DisambiguationLoaded { title, entries } => {
+                self.loading = None;
+                self.current_article_title = title;
+                self.disambiguation = entries;
+                self.disambiguation_state.select(Some(0));
+                self.state = AppState::Disambiguation;
+            }
+            NetworkEvent::SummaryLoaded(title, summary) => {
+                let state = match summary {
+                    Some(summary) => {
+                        if let Some(url) = &summary.thumbnail
+                            && !self.thumbnail_protocols.contains_key(url)
+                        {
+                            self.send(Action::DownloadThumbnail(url.clone()));
+                        }
+                        SummaryState::Ready(summary)
+                    }
+                    None => SummaryState::Failed,
+                };
+                self.summaries.insert(title, state);
+            }
+            NetworkEvent::ArticleImageDownloaded(url, protocol) => {
+                let protocol = self.thread_protocol(url.clone(), false, protocol);
+                self.image_protocols.insert(url, protocol);
+            }
+            NetworkEvent::ArticleImageFailed(url) => {
+                self.image_failures.insert(url);
+            }
+            NetworkEvent::ImageInfoLoaded(file, info) => {
+                let state = match info {
+                    Some(info) => {
+                        if !self.image_protocols.contains_key(&info.url) {
+                            self.send(Action::DownloadImage(info.url.clone()));
+                        }
+                        ImageInfoState::Ready(info)
+                    }
+                    None => ImageInfoState::Failed,
+                };
+                self.image_info.insert(file, state);
+            }
+            NetworkEvent::ThumbnailDownloaded(url, protocol) => {
+                let protocol = self.thread_protocol(url.clone(), true, protocol);
+                self.thumbnail_protocols.insert(url, protocol);
+            }
+            NetworkEvent::ImageResized { url, thumbnail, response } => {
+                let protocols = if thumbnail { &mut self.thumbnail_protocols } else { &mut self.image_protocols };
+                if let Some(protocol) = protocols.get_mut(&url) {
+                    protocol.update_resized_protocol(response);
                 }
-                NetworkEvent::Error(msg) => { self.state = AppState::Error(msg); }
+            }
+            NetworkEvent::FeedLoaded(sections) => {
+                self.feed = match sections {
+                    Some(sections) => FeedState::Ready(sections),
+                    None => FeedState::Failed,
+                };
+            }
+            NetworkEvent::Copied(text, Some(program)) => self.notice = Some(format!("Copied {} characters ({})", text.chars().count(), program)),
+            NetworkEvent::Copied(text, None) => self.copy_to_terminal(&text),
+            NetworkEvent::Notice(message) => self.notice = Some(message),
+            NetworkEvent::ThemeUpdate(new_color) => {
+                self.theme = new_color;
+            }
+            NetworkEvent::Signal("SIGTSTP") => self.external = Some(External::Stop),
+            NetworkEvent::Signal(name) => self.terminated = Some(name),
+            NetworkEvent::Error(msg) => {
+                self.loading = None;
+                self.state = AppState::Error(msg);
             }
         }
     }
 
     fn handle_key(&mut self, key: KeyCode) -> bool {
+        self.notice = None;
+        if key == KeyCode::Esc && self.loading.is_some() && !matches!(self.state, AppState::Searching | AppState::Command) {
+            self.cancel_loading();
+            return false;
+        }
         match self.state {
             AppState::Reading => {
+                if self.pending_key.take() == Some('y') {
+                    if let KeyCode::Char(c) = key { self.yank(c); }
+                    return false;
+                }
                 match key {
                     KeyCode::Char('q') => return true,
                     KeyCode::Esc => { self.state = AppState::ResultsList; }
                     KeyCode::Char('/') => { self.input.clear(); self.state = AppState::Searching; }
                     KeyCode::Char(':') => { self.input.clear(); self.state = AppState::Command; }
+                    KeyCode::Char('r') if !self.references.is_empty() => self.show_references(),
                     KeyCode::Char('c') => { 
                         self.state = AppState::Chapters; 
                         if self.chapter_list_state.selected().is_none() && !self.chapters.is_empty() {
                             self.chapter_list_state.select(Some(0));
                         }
                     }
-                    KeyCode::Char('j') | KeyCode::Down => self.scroll_offset += 1,
-                    KeyCode::Char('k') | KeyCode::Up => if self.scroll_offset > 0 { self.scroll_offset -= 1 },
+                    KeyCode::Char('j') | KeyCode::Down => self.move_cursor(1),
+                    KeyCode::Char('k') | KeyCode::Up => self.move_cursor(-1),
+                    KeyCode::PageDown => self.move_cursor(self.viewport_height as isize),
+                    KeyCode::PageUp => self.move_cursor(-(self.viewport_height as isize)),
+                    KeyCode::Home => self.cursor = 0,
+                    KeyCode::End => self.move_cursor(isize::MAX),
+                    KeyCode::Char('v') => self.start_visual(VisualMode::Char),
+                    KeyCode::Char('V') => self.start_visual(VisualMode::Line),
+                    KeyCode::Char('o') => {
+                        let url = self.link_at_cursor();
+                        self.browse(url);
+                    }
+                    KeyCode::Char('O') => {
+                        let url = self.article_url();
+                        self.browse(Some(url));
+                    }
+                    KeyCode::Char('p') => self.external = Some(External::Pager(self.plain_text())),
+                    KeyCode::Char('e') => self.external = Some(External::Editor(self.plain_text())),
+                    KeyCode::Char('y') => {
+                        self.pending_key = Some('y');
+                        self.notice = Some("Copy: p paragraph  s section  u URL".to_string());
+                    }
+                    KeyCode::Char('h') | KeyCode::Left => self.h_scroll = self.h_scroll.saturating_sub(4),
+                    KeyCode::Char('l') | KeyCode::Right => {
+                        let widest = self.content_blocks.iter().filter_map(|b| match b {
+                            ContentBlock::Table(t) => Some(t.width),
+                            ContentBlock::Code(c) => Some(c.width),
+                            _ => None,
+                        }).max().unwrap_or(0);
+                        self.h_scroll = (self.h_scroll + 4).min(widest.saturating_sub(1));
+                    }
+                    KeyCode::Char('i') => self.inline_images = !self.inline_images,
+                    KeyCode::Char('g') => {
+                        let first_visible = self.viewport_blocks.first().copied().unwrap_or(0);
+                        let figures = self.figure_blocks();
+                        if !figures.is_empty() {
+                            let i = figures.iter().position(|&b| b >= first_visible).unwrap_or(0);
+                            self.open_image(i);
+                        }
+                    }
+                    KeyCode::Char('t') => {
+                        let table = self.viewport_blocks.iter().copied()
+                            .find(|&i| matches!(self.content_blocks.get(i), Some(ContentBlock::Table(_))));
+                        if let Some(i) = table {
+                            self.table_view = Some(i);
+                            self.table_scroll = (0, 0);
+                            self.state = AppState::TableView;
+                        }
+                    }
+                    _ => {}
+                }
+            }
+            AppState::Visual => {
+                match key {
+                    KeyCode::Esc => self.stop_visual(),
+                    KeyCode::Char('v') => self.start_visual(VisualMode::Char),
+                    KeyCode::Char('V') => self.start_visual(VisualMode::Line),
+                    KeyCode::Char('j') | KeyCode::Down => self.move_cursor(1),
+                    KeyCode::Char('k') | KeyCode::Up => self.move_cursor(-1),
+                    KeyCode::PageDown => self.move_cursor(self.viewport_height as isize),
+                    KeyCode::PageUp => self.move_cursor(-(self.viewport_height as isize)),
+                    KeyCode::Char('h') | KeyCode::Left => self.cursor_col = self.cursor_col.saturating_sub(1),
+                    KeyCode::Char('l') | KeyCode::Right => self.cursor_col += 1,
+                    KeyCode::Char('0') | KeyCode::Home => self.cursor_col = 0,
+                    KeyCode::Char('$') | KeyCode::End => self.cursor_col = usize::MAX,
+                    KeyCode::Char('w') => self.word_motion(true),
+                    KeyCode::Char('b') => self.word_motion(false),
+                    KeyCode::Char('o') => {
+                        if let Some((_, anchor)) = self.visual.as_mut() {
+                            let cursor = std::mem::replace(anchor, (self.cursor, self.cursor_col));
+                            (self.cursor, self.cursor_col) = cursor;
+                        }
+                    }
+                    KeyCode::Char('y') => {
+                        let text = self.selection_text();
+                        self.stop_visual();
+                        self.copy(&text);
+                    }
+                    KeyCode::Enter => {
+                        let text = self.selection_text().split_whitespace().collect::<Vec<_>>().join(" ");
+                        self.stop_visual();
+                        if !text.is_empty() { self.navigate(Action::Search(text)); }
+                    }
+                    KeyCode::Char('x') => {
+                        let url = self.link_at_cursor();
+                        self.stop_visual();
+                        self.browse(url);
+                    }
+                    KeyCode::Char('m') => {
+                        self.toggle_highlight();
+                        self.stop_visual();
+                    }
+                    KeyCode::Char('r') if !self.references.is_empty() => self.show_references(),
+                    KeyCode::Char('n') => {
+                        self.note_quote = Some(self.selection_text());
+                        self.visual = None;
+                        self.input = "note ".to_string();
+                        self.state = AppState::Command;
+                    }
+                    _ => {}
+                }
+                self.clamp_cursor_col();
+            }
+            AppState::ImageView => {
+                let count = self.figure_blocks().len();
+                match key {
+                    KeyCode::Esc | KeyCode::Char('g') => { self.state = AppState::Reading; }
+                    KeyCode::Char('q') => return true,
+                    KeyCode::Char('n') | KeyCode::Char('l') | KeyCode::Char('j') | KeyCode::Right | KeyCode::Down => {
+                        self.open_image((self.image_view + 1) % count.max(1));
+                    }
+                    KeyCode::Char('p') | KeyCode::Char('h') | KeyCode::Char('k') | KeyCode::Left | KeyCode::Up => {
+                        self.open_image((self.image_view + count.max(1) - 1) % count.max(1));
+                    }
+                    KeyCode::Enter | KeyCode::Char('o') => {
+                        let url = self.current_figure().and_then(|f| self.figure_page(f));
+                        self.browse(url);
+                    }
+                    _ => {}
+                }
+            }
+            AppState::TableView => {
+                let width = match self.table_view.and_then(|i| self.content_blocks.get(i)) {
+                    Some(ContentBlock::Table(t)) => t.width,
+                    _ => 0,
+                };
+                match key {
+                    KeyCode::Esc | KeyCode::Char('t') => { self.state = AppState::Reading; }
+                    KeyCode::Char('q') => return true,
+                    KeyCode::Char('j') | KeyCode::Down => self.table_scroll.0 += 1,
+                    KeyCode::Char('k') | KeyCode::Up => self.table_scroll.0 = self.table_scroll.0.saturating_sub(1),
+                    KeyCode::Char('h') | KeyCode::Left => self.table_scroll.1 = self.table_scroll.1.saturating_sub(4),
+                    KeyCode::Char('l') | KeyCode::Right => self.table_scroll.1 = (self.table_scroll.1 + 4).min(width.saturating_sub(1)),
                     _ => {}
                 }
             }
@@ -554,23 +1528,71 @@ impl App {
                     }
                     KeyCode::Enter => {
                         if let Some(i) = self.chapter_list_state.selected() {
-                            if let Some((_, _, block_idx)) = self.chapters.get(i) {
-                                self.scroll_offset = (*block_idx as u16) * 10;
-                            }
+                            self.jump_to_chapter(i);
                         }
                         self.state = AppState::Reading;
                     }
                     _ => {}
                 }
             }
+            AppState::References => {
+                match key {
+                    KeyCode::Esc | KeyCode::Char('r') => { self.state = AppState::Reading; }
+                    KeyCode::Char('q') => return true,
+                    KeyCode::Char('j') | KeyCode::Down => {
+                        let i = self.references_state.selected().unwrap_or(0);
+                        if i < self.references.len().saturating_sub(1) {
+                            self.references_state.select(Some(i + 1));
+                        }
+                    }
+                    KeyCode::Char('k') | KeyCode::Up => {
+                        let i = self.references_state.selected().unwrap_or(0);
+                        self.references_state.select(Some(i.saturating_sub(1)));
+                    }
+                    KeyCode::Enter | KeyCode::Char('o') => {
+                        let url = self.references_state.selected().and_then(|i| self.references.get(i)).and_then(|r| r.url.clone());
+                        self.browse(url);
+                    }
+                    _ => {}
+                }
+            }
+            AppState::Disambiguation => {
+                match key {
+                    KeyCode::Char('q') => return true,
+                    KeyCode::Esc => { self.state = AppState::ResultsList; }
+                    KeyCode::Char('/') => { self.input.clear(); self.state = AppState::Searching; }
+                    KeyCode::Char('j') | KeyCode::Down => {
+                        let i = self.disambiguation_state.selected().unwrap_or(0);
+                        if i < self.disambiguation.len().saturating_sub(1) {
+                            self.disambiguation_state.select(Some(i + 1));
+                        }
+                    }
+                    KeyCode::Char('k') | KeyCode::Up => {
+                        let i = self.disambiguation_state.selected().unwrap_or(0);
+                        self.disambiguation_state.select(Some(i.saturating_sub(1)));
+                    }
+                    KeyCode::Enter => {
+                        if let Some(entry) = self.disambiguation_state.selected().and_then(|i| self.disambiguation.get(i)) {
+                            self.navigate(Action::FetchArticle(entry.title.clone()));
+                        }
+                    }
+                    _ => {}
+                }
+            }
             AppState::Command => {
                 match key {
-                    KeyCode::Esc => { self.state = AppState::Reading; self.input.clear(); }
+                    KeyCode::Esc => { self.state = AppState::Reading; self.input.clear(); self.note_quote = None; }
                     KeyCode::Enter => {
-                        if let Ok(idx) = self.input.parse::<usize>() {
-                            if let Some((_, _, block_idx)) = self.chapters.iter().find(|(i, _, _)| *i == idx) {
-                                self.scroll_offset = (*block_idx as u16) * 10; 
-                            }
+                        if let Some(rest) = self.input.strip_prefix("export") {
+                            let rest = rest.trim().to_string();
+                            self.export(&rest);
+                        } else if let Some(rest) = self.input.strip_prefix("note") {
+                            let rest = rest.trim().to_string();
+                            self.note(&rest);
+                        } else if let Ok(idx) = self.input.parse::<usize>()
+                            && let Some(i) = self.chapters.iter().position(|(n, _, _)| *n == idx)
+                        {
+                            self.jump_to_chapter(i);
                         }
                         self.state = AppState::Reading;
                         self.input.clear();
@@ -586,17 +1608,34 @@ impl App {
                         self.state = AppState::Home; 
                         self.input.clear();
                     }
-                    KeyCode::Enter => {
-                        if !self.input.is_empty() {
-                            self.state = AppState::Loading;
-                            let _ = self.action_tx.send(Action::Search(self.input.clone()));
-                        }
+                    KeyCode::Enter if !self.input.is_empty() => {
+                        self.state = if self.content_blocks.is_empty() { AppState::Home } else { AppState::Reading };
+                        self.navigate(Action::Search(self.input.clone()));
                     }
                     KeyCode::Backspace => { self.input.pop(); },
                     KeyCode::Char(c) => self.input.push(c),
                     _ => {}
                 }
             }
+            AppState::Home => {
+                match key {
+                    KeyCode::Char('q') => return true,
+                    KeyCode::Char('/') => { self.input.clear(); self.state = AppState::Searching; }
+                    KeyCode::Char('?') => self.show_controls = !self.show_controls,
+                    KeyCode::Esc => self.show_controls = false,
+                    KeyCode::Char('r') => self.navigate(Action::FetchRandom),
+                    KeyCode::Char('j') | KeyCode::Down => {
+                        self.home_index = (self.home_index + 1).min(self.discover_targets().len().saturating_sub(1));
+                    }
+                    KeyCode::Char('k') | KeyCode::Up => self.home_index = self.home_index.saturating_sub(1),
+                    KeyCode::Enter => match self.discover_targets().get(self.home_index) {
+                        Some(Some(title)) => self.navigate(Action::FetchArticle(title.to_string())),
+                        Some(None) => self.navigate(Action::FetchRandom),
+                        None => {}
+                    },
+                    _ => {}
+                }
+            }
             _ => {
                 match key {
                     KeyCode::Char('q') => return true,
@@ -612,33 +1651,485 @@ impl App {
         false
     }
 
-    fn handle_mouse(&mut self, mouse: event::MouseEvent) {
-        if mouse.kind == MouseEventKind::Down(event::MouseButton::Left) {
+    fn export(&mut self, command: &str) {
+        let (format, path) = command.split_once(char::is_whitespace).unwrap_or((command, ""));
+        let path = match path.trim() {
+            "" => PathBuf::from(format!("{}.{}", self.current_article_title.replace(['/', '\\'], "-"), format)),
+            p => match p.strip_prefix("~/").zip(dirs::home_dir()) {
+                Some((rest, home)) => home.join(rest),
+                None => PathBuf::from(p),
+            },
+        };
+        let result = match format {
+            _ if self.content_blocks.is_empty() => Err(anyhow!("no article is open")),
+            "md" | "markdown" => {
+                let text = markdown::article_markdown(&self.site, &self.current_article_title, &self.current_article_info, &self.content_blocks, &self.references, false);
+                std::fs::write(&path, text).map_err(Into::into)
+            }
+            "epub" => {
+                let article = epub::Article::new(&self.site, &self.current_article_title, &self.current_article_info, &self.content_blocks, &self.references);
+                self.send(Action::ExportEpub(path.clone(), article));
+                self.notice = Some(format!("Exporting {}", path.display()));
+                return;
+            }
+            _ => Err(anyhow!("unknown format '{}', use :export md|epub <path>", format)),
+        };
+        self.notice = Some(match result {
+            Ok(()) => format!("Exported to {}", path.display()),
+            Err(e) => format!("Export failed: {}", e),
+        });
+    }
+
+    fn move_cursor(&mut self, delta: isize) {
+        let rows = self.layout.as_ref().map_or(0, |l| l.rows.len());
+        self.cursor = self.cursor.saturating_add_signed(delta).min(rows.saturating_sub(1));
+    }
+
+    fn unit_text(&self, block: usize, line: usize) -> String {
+        match &self.content_blocks[block] {
+            ContentBlock::Text(lines) => {
+                let text = lines[line].plain().trim().to_string();
+                match &lines[line].kind {
+                    LineKind::ListItem { indent, marker } if !marker.is_empty() => format!("{}{} {}", " ".repeat(*indent), marker, text),
+                    LineKind::Quote(_) => format!("> {}", text),
+                    _ => text,
+                }
+            }
+            ContentBlock::Image(figure) => figure.caption.clone().unwrap_or_else(|| figure.url.clone()),
+            ContentBlock::Table(table) => table.grid.iter().map(|row| row.join("\t")).collect::<Vec<_>>().join("\n"),
+            ContentBlock::Code(code) => code.source.clone(),
+            ContentBlock::Math { tex, unicode } => unicode.clone().unwrap_or_else(|| tex.clone()),
+        }
+    }
+
+    fn rows_text(&self, start: usize, end: usize) -> String {
+        let Some(layout) = &self.layout else { return String::new(); };
+        let mut units: Vec<(usize, usize)> = Vec::new();
+        for row in &layout.rows[start.min(layout.rows.len())..end.min(layout.rows.len())] {
+            if units.last() != Some(&(row.block, row.line)) { units.push((row.block, row.line)); }
+        }
+        units.into_iter()
+            .map(|(block, line)| self.unit_text(block, line))
+            .filter(|text| !text.is_empty())
+            .collect::<Vec<_>>()
+            .join("\n")
+    }
+
+    fn yank(&mut self, what: char) {
+        let text = match what {
+            'u' => Some(self.site.page_url(&self.current_article_title)),
+            'p' | 'y' => self.layout.as_ref().and_then(|l| l.rows.get(self.cursor)).map(|row| self.unit_text(row.block, row.line)),
+            's' => self.layout.as_ref().map(|layout| {
+                let (start, end) = match layout.chapter_rows.iter().rposition(|&row| row <= self.cursor) {
+                    Some(i) => output::section_rows(layout, &self.content_blocks, i),
+                    None => (0, layout.chapter_rows.first().copied().unwrap_or(layout.rows.len())),
+                };
+                self.rows_text(start, end)
+            }),
+            _ => None,
+        };
+        if let Some(text) = text { self.copy(&text); }
+    }
+
+    fn copy(&mut self, text: &str) {
+        if text.trim().is_empty() { return; }
+        if clipboard::remote() { return self.copy_to_terminal(text); }
+        let event_tx = self.event_tx.clone();
+        let text = text.to_string();
+        tokio::task::spawn_blocking(move || {
+            let program = clipboard::system(&text);
+            let _ = event_tx.send((0, NetworkEvent::Copied(text, program)));
+        });
+    }
+
+    fn copy_to_terminal(&mut self, text: &str) {
+        self.notice = Some(match clipboard::osc52(text) {
+            Ok(()) => format!("Sent {} characters to the terminal clipboard", text.chars().count()),
+            Err(_) => "No clipboard available".to_string(),
+        });
+    }
+
+    fn plain_text(&self) -> String {
+        let width = self.layout.as_ref().map_or(80, |l| l.width);
+        output::article_text(&self.current_article_title, &self.current_article_info, &self.content_blocks, &self.references, width, false)
+            .unwrap_or_default()
+    }
+
+    fn run_external(&mut self, external: External) {
+        let result = match external {
+            External::Pager(text) => external::page(&text),
+            External::Editor(text) => external::edit(&text, &self.current_article_title),
+            External::Browser(url) => external::browse(&url),
+            External::Stop => {
+                #[cfg(unix)]
+                unsafe { libc::raise(libc::SIGSTOP); }
+                Ok(())
+            }
+        };
+        if let Err(e) = result {
+            self.notice = Some(format!("Could not run external program: {}", e));
+        }
+    }
+
+    fn browse(&mut self, url: Option<String>) {
+        self.notice = Some(match url {
+            Some(url) if external::terminal_browser() => {
+                self.external = Some(External::Browser(url));
+                return;
+            }
+            Some(url) if external::open_url(&url) => format!("Opening {}", url),
+            Some(_) => "Could not start a browser; set $BROWSER".to_string(),
+            None => "No link here".to_string(),
+        });
+    }
+
+    fn figure_page(&self, figure: &Figure) -> Option<String> {
+        let file = figure.file.as_ref()?;
+        match self.image_info.get(file) {
+            Some(ImageInfoState::Ready(info)) if !info.page_url.is_empty() => Some(info.page_url.clone()),
+            _ => Some(self.site.page_url(&format!("File:{}", file))),
+        }
+    }
+
+    fn article_url(&self) -> String {
+        let url = self.site.page_url(&self.current_article_title);
+        let section = self.layout.as_ref()
+            .and_then(|l| l.chapter_rows.iter().rposition(|&row| row <= self.cursor))
+            .and_then(|i| self.chapters.get(i));
+        match section {
+            Some((_, name, _)) => format!("{}#{}", url, name.trim().replace(' ', "_")),
+            None => url,
+        }
+    }
+
+    fn show_references(&mut self) {
+        let selected = self.citation_at_cursor()
+            .or(self.references_state.selected())
+            .unwrap_or(0);
+        self.references_state.select(Some(selected));
+        self.visual = None;
+        self.state = AppState::References;
+    }
+
+    fn link_at_cursor(&self) -> Option<String> {
+        let row = self.layout.as_ref()?.rows.get(self.cursor)?;
+        if let ContentBlock::Image(figure) = &self.content_blocks[row.block] { return self.figure_page(figure); }
+        let href = self.style_at_cursor(|style| style.link.as_deref())?;
+        self.site.link_url(&self.current_article_title, href)
+    }
+
+    fn citation_at_cursor(&self) -> Option<usize> {
+        let label = self.style_at_cursor(|style| style.citation.as_deref())?;
+        self.references.iter().position(|r| r.label == label)
+    }
+
+    fn style_at_cursor<'a, T>(&'a self, pick: impl Fn(&'a InlineStyle) -> Option<T>) -> Option<T> {
+        let layout = self.layout.as_ref()?;
+        let row = layout.rows.get(self.cursor)?;
+        let ContentBlock::Text(lines) = &self.content_blocks[row.block] else { return None; };
+        let line = &lines[row.line];
+        let indent = row_indent(row, &self.content_blocks);
+        let offset = unit_offset(layout, &self.content_blocks, self.cursor);
+        let end = offset + row_text(row, &self.content_blocks).chars().count().saturating_sub(indent);
+        let skip = line.plain().chars().take_while(|c| c.is_whitespace()).count();
+        let mut pos: usize = 0;
+        let mut found = Vec::new();
+        for (text, style) in &line.spans {
+            let (start, stop) = (pos.saturating_sub(skip), (pos + text.chars().count()).saturating_sub(skip));
+            pos += text.chars().count();
+            if start < end && stop > offset
+                && let Some(value) = pick(style)
+            {
+                found.push((start, stop, value));
+            }
+        }
+        let col = offset + self.cursor_col.saturating_sub(indent);
+        let index = found.iter().position(|(start, stop, _)| self.visual.is_some() && (*start..*stop).contains(&col)).unwrap_or(0);
+        found.into_iter().nth(index).map(|(_, _, value)| value)
+    }
+
+    fn start_visual(&mut self, mode: VisualMode) {
+        match self.visual {
+            Some((current, _)) if current == mode => self.stop_visual(),
+            Some((_, anchor)) => self.visual = Some((mode, anchor)),
+            None => {
+                self.cursor_col = 0;
+                self.clamp_cursor_col();
+                self.visual = Some((mode, (self.cursor, self.cursor_col)));
+                self.state = AppState::Visual;
+            }
+        }
+    }
+
+    fn stop_visual(&mut self) {
+        self.visual = None;
+        if let AppState::Visual = self.state { self.state = AppState::Reading; }
+    }
+
+    fn cursor_row(&self) -> (Vec<char>, usize) {
+        match self.layout.as_ref().and_then(|l| l.rows.get(self.cursor)) {
+            Some(row) => (row_text(row, &self.content_blocks).chars().collect(), row_indent(row, &self.content_blocks)),
+            None => (Vec::new(), 0),
+        }
+    }
+
+    fn clamp_cursor_col(&mut self) {
+        let (chars, indent) = self.cursor_row();
+        self.cursor_col = self.cursor_col.min(chars.len().saturating_sub(1)).max(indent);
+    }
+
+    fn word_motion(&mut self, forward: bool) {
+        let (chars, indent) = self.cursor_row();
+        let starts = |i: usize| !chars[i].is_whitespace() && (i == indent || chars[i - 1].is_whitespace());
+        let col = self.cursor_col.min(chars.len());
+        let found = match forward {
+            true => (col + 1..chars.len()).find(|&i| starts(i)),
+            false => (indent..col).rev().find(|&i| starts(i)),
+        };
+        match found {
+            Some(i) => self.cursor_col = i,
+            None if forward => { self.move_cursor(1); self.cursor_col = 0; }
+            None if self.cursor > 0 => { self.move_cursor(-1); self.cursor_col = usize::MAX; }
+            None => self.cursor_col = 0,
+        }
+        self.clamp_cursor_col();
+    }
+
+    fn selection(&self) -> Option<Selection> {
+        let (mode, anchor) = self.visual?;
+        let cursor = (self.cursor, self.cursor_col);
+        let (start, end) = if anchor <= cursor { (anchor, cursor) } else { (cursor, anchor) };
+        Some(match mode {
+            VisualMode::Char => (mode, start, end),
+            VisualMode::Line => (mode, (start.0, 0), (end.0, usize::MAX - 1)),
+        })
+    }
+
+    fn selected_rows(&self) -> Vec<(usize, usize, usize)> {
+        let (Some(layout), Some((_, (r1, c1), (r2, c2)))) = (&self.layout, self.selection()) else { return Vec::new(); };
+        (r1..=r2.min(layout.rows.len().saturating_sub(1))).filter_map(|i| {
+            let row = &layout.rows[i];
+            let len = row_text(row, &self.content_blocks).chars().count();
+            let indent = row_indent(row, &self.content_blocks);
+            let from = if i == r1 { c1.max(indent) } else { indent };
+            let to = if i == r2 { c2.saturating_add(1).min(len) } else { len };
+            (from < to).then_some((i, from, to))
+        }).collect()
+    }
+
+    fn selection_text(&self) -> String {
+        let Some(layout) = &self.layout else { return String::new(); };
+        let mut out = String::new();
+        let mut previous = None;
+        for (i, from, to) in self.selected_rows() {
+            let row = &layout.rows[i];
+            let unit = (row.block, row.line);
+            if previous.is_some() {
+                let joined = previous == Some(unit) && matches!(self.content_blocks[row.block], ContentBlock::Text(_));
+                out.push(if joined { ' ' } else { '\n' });
+            }
+            out.extend(row_text(row, &self.content_blocks).chars().skip(from).take(to - from));
+            out.truncate(out.trim_end().len());
+            previous = Some(unit);
         }
+        out
+    }
+
+    fn toggle_highlight(&mut self) {
+        let Some(layout) = &self.layout else { return; };
+        let mut marks: Vec<Mark> = Vec::new();
+        for (i, from, to) in self.selected_rows() {
+            let row = &layout.rows[i];
+            if !matches!((&row.content, &self.content_blocks[row.block]), (RowContent::Line(_), ContentBlock::Text(_))) { continue; }
+            let offset = unit_offset(layout, &self.content_blocks, i);
+            let indent = row_indent(row, &self.content_blocks);
+            let (start, end) = (offset + from - indent, offset + to - indent);
+            match marks.last_mut() {
+                Some(last) if (last.0, last.1) == (row.block, row.line) => last.3 = end,
+                _ => marks.push((row.block, row.line, start, end)),
+            }
+        }
+        if marks.is_empty() { return; }
+        let units = layout_units(layout, &self.content_blocks);
+        let overlaps = |h: &Mark| marks.iter().any(|m| (m.0, m.1) == (h.0, h.1) && m.2 < h.3 && h.2 < m.3);
+        if self.highlight_marks.iter().any(overlaps) {
+            self.highlights.retain(|h| !locate_highlight(&units, h).is_some_and(|m| overlaps(&m)));
+            self.notice = Some("Highlight removed".to_string());
+        } else {
+            self.highlights.extend(marks.iter().filter_map(|m| {
+                let chars: Vec<char> = units.iter().find(|(unit, _)| *unit == (m.0, m.1))?.1.chars().collect();
+                Some(Highlight {
+                    quote: chars[m.2..m.3.min(chars.len())].iter().collect(),
+                    before: chars[m.2.saturating_sub(HIGHLIGHT_CONTEXT)..m.2].iter().collect(),
+                    after: chars[m.3.min(chars.len())..(m.3 + HIGHLIGHT_CONTEXT).min(chars.len())].iter().collect(),
+                })
+            }));
+            self.notice = Some("Highlighted".to_string());
+        }
+        self.highlight_marks = self.highlights.iter().filter_map(|h| locate_highlight(&units, h)).collect();
+        self.site.store_highlights(&self.current_article_title, &self.highlights);
+    }
+
+    fn note(&mut self, text: &str) {
+        let quote = self.note_quote.take()
+            .or_else(|| self.layout.as_ref().and_then(|l| l.rows.get(self.cursor)).map(|row| self.unit_text(row.block, row.line)))
+            .unwrap_or_default();
+        let Some(path) = dirs::data_dir().map(|dir| dir.join("rwiki").join("notes.md")) else { return; };
+        let mut entry = format!("## {}\n\n", self.current_article_title);
+        for line in quote.lines().filter(|l| !l.trim().is_empty()) {
+            entry.push_str(&format!("> {}\n", line.trim()));
+        }
+        if !text.is_empty() {
+            entry.push_str(&format!("\n{}\n", text));
+        }
+        entry.push_str(&format!("\n<{}>\n\n", self.site.page_url(&self.current_article_title)));
+        let written = std::fs::create_dir_all(path.parent().unwrap_or(&path)).and_then(|_| {
+            std::fs::OpenOptions::new().create(true).append(true).open(&path)?.write_all(entry.as_bytes())
+        });
+        self.notice = Some(match written {
+            Ok(()) => format!("Note saved to {}", path.display()),
+            Err(e) => format!("Could not save note: {}", e),
+        });
+    }
+
+    fn jump_to_chapter(&mut self, i: usize) {
+        if i < self.chapters.len() {
+            self.pending_chapter = Some(i);
+            self.chapter_list_state.select(Some(i));
+        }
+    }
+
+    fn send(&self, action: Action) {
+        let generation = match action {
+            Action::DownloadImage(_) | Action::FetchImageInfo(_) => self.article_generation,
+            Action::FetchSummary(_) | Action::DownloadThumbnail(_) => self.results_generation,
+            Action::FetchFeed | Action::ExportEpub(..) => 0,
+            _ => self.generation,
+        };
+        let _ = self.action_tx.send((generation, action));
+    }
+
+    fn navigate(&mut self, action: Action) {
+        self.generation += 1;
+        self.loading = match &action {
+            Action::Search(query) => Some(format!("Searching for \"{}\"", query)),
+            Action::FetchArticle(title) => Some(format!("Loading {}", title)),
+            Action::FetchRandom => Some("Loading a random article".to_string()),
+            _ => None,
+        }.map(|label| (label, std::time::Instant::now()));
+        self.cancel_stale();
+        self.send(action);
+    }
+
+    fn cancel_loading(&mut self) {
+        self.loading = None;
+        self.generation += 1;
+        self.cancel_stale();
+    }
+
+    fn cancel_stale(&self) {
+        self.send(Action::Cancel(vec![self.article_generation, self.results_generation]));
+    }
+
+    fn image_status(&self, url: &str) -> String {
+        if self.image_failures.contains(url) {
+            "[Image unavailable]".to_string()
+        } else {
+            format!("{} Loading image", SPINNER[self.tick % SPINNER.len()])
+        }
+    }
+
+    fn thread_protocol(&self, url: String, thumbnail: bool, protocol: StatefulProtocol) -> ThreadProtocol {
+        let (tx, mut rx) = mpsc::unbounded_channel::<ratatui_image::thread::ResizeRequest>();
+        let event_tx = self.event_tx.clone();
+        tokio::spawn(async move {
+            while let Some(request) = rx.recv().await {
+                let Ok(Ok(response)) = tokio::task::spawn_blocking(move || request.resize_encode()).await else { continue; };
+                if event_tx.send((0, NetworkEvent::ImageResized { url: url.clone(), thumbnail, response })).is_err() { break; }
+            }
+        });
+        ThreadProtocol::new(tx, Some(protocol))
+    }
+
+    fn figure_blocks(&self) -> Vec<usize> {
+        self.content_blocks.iter().enumerate()
+            .filter(|(_, b)| matches!(b, ContentBlock::Image(_)))
+            .map(|(i, _)| i)
+            .collect()
+    }
+
+    fn current_figure(&self) -> Option<&Figure> {
+        match self.figure_blocks().get(self.image_view).and_then(|&b| self.content_blocks.get(b)) {
+            Some(ContentBlock::Image(figure)) => Some(figure),
+            _ => None,
+        }
+    }
+
+    fn open_image(&mut self, i: usize) {
+        self.image_view = i;
+        self.state = AppState::ImageView;
+        if let Some(file) = self.current_figure().and_then(|f| f.file.clone())
+            && !self.image_info.contains_key(&file)
+        {
+            self.image_info.insert(file.clone(), ImageInfoState::Loading);
+            self.send(Action::FetchImageInfo(file));
+        }
+    }
+
+    fn discover_targets(&self) -> Vec<Option<&str>> {
+        let mut targets = vec![None];
+        if let FeedState::Ready(sections) = &self.feed {
+            targets.extend(sections.iter().flat_map(|s| &s.items).map(|item| Some(item.title.as_str())));
+        }
+        targets
+    }
+
+    fn handle_mouse(&mut self, mouse: event::MouseEvent) {
+        let key = match mouse.kind {
+            MouseEventKind::ScrollDown => KeyCode::Down,
+            MouseEventKind::ScrollUp => KeyCode::Up,
+            _ => return,
+        };
+        let steps = match self.state {
+            AppState::Reading | AppState::Visual | AppState::TableView => 3,
+            AppState::Searching | AppState::Command => 0,
+            _ => 1,
+        };
+        for _ in 0..steps { self.handle_key(key); }
     }
 
     fn move_down(&mut self) {
         if let AppState::ResultsList = self.state {
             if !self.search_results.is_empty() && self.selected_index < self.search_results.len() - 1 { self.selected_index += 1; }
+            self.request_summary();
         }
     }
 
     fn move_up(&mut self) {
         if let AppState::ResultsList = self.state {
             if self.selected_index > 0 { self.selected_index -= 1; }
+            self.request_summary();
+        }
+    }
+
+    fn request_summary(&mut self) {
+        if let Some(item) = self.search_results.get(self.selected_index)
+            && !self.summaries.contains_key(&item.title)
+        {
+            self.summaries.insert(item.title.clone(), SummaryState::Loading);
+            self.send(Action::FetchSummary(item.title.clone()));
         }
     }
 
     fn select_item(&mut self) {
         if let Some(item) = self.search_results.get(self.selected_index) {
-            self.state = AppState::Loading;
-            let _ = self.action_tx.send(Action::FetchArticle(item.title.clone()));
+            self.navigate(Action::FetchArticle(item.title.clone()));
         }
     }
 }
 
 fn ui(f: &mut Frame, app: &mut App) {
-    let (main_area, bottom_area) = if matches!(app.state, AppState::Home | AppState::Reading | AppState::Chapters) {
+    let (main_area, bottom_area) = if matches!(app.state, AppState::Home | AppState::Reading | AppState::Visual | AppState::Chapters | AppState::References | AppState::TableView) {
         let c = Layout::vertical([Constraint::Min(0)]).split(f.area()); (c[0], Rect::default())
     } else {
         let c = Layout::vertical([Constraint::Min(0), Constraint::Length(3)]).split(f.area()); (c[0], c[1])
@@ -650,6 +2141,7 @@ fn ui(f: &mut Frame, app: &mut App) {
     };
 
     match &app.state {
+        AppState::Home if !app.show_controls => render_discover(f, app, main_area, border),
         AppState::Home => {
             let t = vec![
                 Line::from(vec![Span::styled("Welcome to rwiki", Style::default().fg(app.theme).add_modifier(Modifier::BOLD))]),
@@ -658,12 +2150,22 @@ fn ui(f: &mut Frame, app: &mut App) {
                 Line::from("────────"),
                 Line::from("  /      : Search"),
                 Line::from("  Enter  : Select Article"),
-                Line::from("  j / k  : Scroll"),
+                Line::from("  j / k  : Move Cursor"),
                 Line::from("  :      : Jump to Chapter"),
+                Line::from("  :export md|epub <path> : Save as Markdown or EPUB"),
                 Line::from("  c      : Chapters Mode"),
+                Line::from("  r      : References"),
+                Line::from("  h / l  : Scroll Tables & Code"),
+                Line::from("  t      : Open Table"),
+                Line::from("  y p/s/u : Copy Paragraph, Section or URL"),
+                Line::from("  v / V  : Select Text to Copy, Look up, Highlight or Note"),
+                Line::from("  o / O  : Open Link or Article in Browser"),
+                Line::from("  p / e  : Open in $PAGER or $EDITOR"),
+                Line::from("  i      : Inline Images"),
+                Line::from("  g      : Image Viewer"),
                 Line::from("  q      : Quit"),
             ];
-            f.render_widget(Paragraph::new(t).alignment(Alignment::Center).block(border("Home")), main_area);
+            f.render_widget(Paragraph::new(t).alignment(Alignment::Center).block(border("Controls")), main_area);
         }
         AppState::Searching => {
             f.render_widget(Paragraph::new(app.input.as_str()).style(Style::default().fg(app.theme)).block(border("Search Query")), bottom_area);
@@ -674,118 +2176,600 @@ fn ui(f: &mut Frame, app: &mut App) {
             f.render_widget(Paragraph::new(cmd_text).style(Style::default().fg(Color::Cyan)).block(border("Command")), bottom_area);
             render_reading_view(f, app, main_area, border); 
         }
-        AppState::Loading => {
-            f.render_widget(Paragraph::new("Fetching...").alignment(Alignment::Center).style(Style::default().fg(app.theme).add_modifier(Modifier::RAPID_BLINK)), main_area);
-        }
         AppState::ResultsList => {
+            let chunks = Layout::horizontal([Constraint::Percentage(40), Constraint::Percentage(60)]).split(main_area);
             let items: Vec<ListItem> = app.search_results.iter().enumerate().map(|(i, r)| {
                 let style = if i == app.selected_index { Style::default().fg(Color::Black).bg(app.theme) } else { Style::default() };
                 ListItem::new(format!(" {} ", r.title)).style(style)
             }).collect();
-            f.render_widget(List::new(items).block(border("Search Results")), main_area);
+            f.render_widget(List::new(items).block(border("Search Results")), chunks[0]);
+            render_summary_preview(f, app, chunks[1], border);
+        }
+        AppState::Disambiguation => {
+            let items: Vec<ListItem> = app.disambiguation.iter().map(|entry| {
+                let mut lines = vec![Line::from(Span::styled(format!(" {} ", entry.title), Style::default().add_modifier(Modifier::BOLD)))];
+                if !entry.description.is_empty() && entry.description != entry.title {
+                    lines.push(Line::from(Span::styled(format!("   {}", entry.description), Style::default().fg(Color::DarkGray))));
+                }
+                ListItem::new(lines)
+            }).collect();
+            let title = format!("{} may refer to", app.current_article_title);
+            let list = List::new(items).block(border(&title)).highlight_style(Style::default().fg(Color::Black).bg(app.theme));
+            f.render_stateful_widget(list, main_area, &mut app.disambiguation_state);
         }
-        AppState::Reading | AppState::Chapters => {
+        AppState::Reading | AppState::Visual | AppState::Chapters => {
             render_reading_view(f, app, main_area, border);
         }
+        AppState::References => {
+            render_reading_view(f, app, main_area, border);
+            render_references(f, app, main_area, border);
+        }
+        AppState::TableView => {
+            if let Some(ContentBlock::Table(table)) = app.table_view.and_then(|i| app.content_blocks.get(i)) {
+                let block = border(table.caption.as_deref().unwrap_or("Table"))
+                    .title_bottom(Span::styled(" h/j/k/l: Scroll  Esc: Close ", Style::default().fg(Color::DarkGray)));
+                let inner = block.inner(main_area);
+                let lines: Vec<Line> = table.lines.iter()
+                    .skip(app.table_scroll.0 as usize)
+                    .take(inner.height as usize)
+                    .map(|line| table::styled_line(line, app.table_scroll.1, inner.width as usize, app.theme))
+                    .collect();
+                f.render_widget(Paragraph::new(lines).block(block), main_area);
+            }
+        }
+        AppState::ImageView => render_image_view(f, app, main_area, border),
         AppState::Error(msg) => {
             f.render_widget(Paragraph::new(format!("Error: {}", msg)).style(Style::default().fg(Color::Red)).block(Block::default().borders(Borders::ALL)), main_area);
         }
     }
 
-    if matches!(app.state, AppState::Reading | AppState::Chapters) {
+    if matches!(app.state, AppState::Reading | AppState::Visual | AppState::Chapters | AppState::References | AppState::TableView | AppState::ImageView) {
     } else if !matches!(app.state, AppState::Searching | AppState::Command) {
         f.render_widget(Paragraph::new(" [ /: Search ] [ q: Quit ] [ Enter: Select ] ").style(Style::default().bg(app.theme).fg(Color::Black)), bottom_area);
     }
+
+    if let Some((label, started)) = &app.loading {
+        let elapsed = started.elapsed();
+        let frame = SPINNER[(elapsed.as_millis() / 100) as usize % SPINNER.len()];
+        render_status(f, &format!(" {} {} ({:.1}s)  Esc: Cancel ", frame, label, elapsed.as_secs_f32()), app.theme);
+    } else if let Some(notice) = &app.notice {
+        render_status(f, &format!(" {} ", notice), app.theme);
+    } else if let Some((mode, _)) = app.visual {
+        let mode = if mode == VisualMode::Line { "VISUAL LINE" } else { "VISUAL" };
+        render_status(f, &format!(" -- {} --  y: Copy  Enter: Look up  x: Open link  m: Highlight  n: Note ", mode), app.theme);
+    }
+}
+
+fn render_status(f: &mut Frame, text: &str, theme: Color) {
+    let area = f.area();
+    let width = (text.chars().count() as u16).min(area.width);
+    let rect = Rect::new(area.right() - width, area.bottom().saturating_sub(1), width, 1);
+    f.render_widget(Clear, rect);
+    f.render_widget(Paragraph::new(text.to_string()).style(Style::default().bg(theme).fg(Color::Black)), rect);
+}
+
+fn render_discover<F>(f: &mut Frame, app: &mut App, area: Rect, border: F)
+where F: Fn(&str) -> Block<'static>
+{
+    let block = border("Discover")
+        .title_bottom(Span::styled(" j/k: Move  Enter: Open  r: Random  /: Search  ?: Controls  q: Quit ", Style::default().fg(Color::DarkGray)));
+    let width = block.inner(area).width.saturating_sub(4).max(1) as usize;
+    let dim = Style::default().fg(Color::DarkGray);
+    let selected = Style::default().fg(Color::Black).bg(app.theme);
+
+    let mut items = Vec::new();
+    let mut selected_item = 0;
+    let mut target = 0;
+    let mut entry = |items: &mut Vec<ListItem<'static>>, lines: Vec<Line<'static>>| {
+        if target == app.home_index {
+            selected_item = items.len();
+            items.push(ListItem::new(lines).style(selected));
+        } else {
+            items.push(ListItem::new(lines));
+        }
+        target += 1;
+    };
+
+    entry(&mut items, vec![Line::from(Span::styled(" ⚄ Random article", Style::default().add_modifier(Modifier::BOLD)))]);
+    match &app.feed {
+        FeedState::Loading => items.push(ListItem::new(Line::styled(" Loading today's articles...", dim))),
+        FeedState::Failed => items.push(ListItem::new(Line::styled(" Today's featured content is not available for this site.", dim))),
+        FeedState::Ready(sections) => {
+            for section in sections {
+                items.push(ListItem::new(""));
+                items.push(ListItem::new(Line::styled(format!(" {}", section.kind.name()), Style::default().fg(app.theme).add_modifier(Modifier::BOLD))));
+                for item in &section.items {
+                    let lines = match section.kind {
+                        feed::Kind::News | feed::Kind::OnThisDay => textwrap::wrap(&item.text, width).into_iter().take(3).enumerate()
+                            .map(|(i, l)| Line::raw(format!("{}{}", if i == 0 { "  • " } else { "    " }, l)))
+                            .collect(),
+                        _ => {
+                            let mut heading = vec![Span::styled(format!("  {}", item.title), Style::default().add_modifier(Modifier::BOLD))];
+                            if !item.text.is_empty() { heading.push(Span::raw(format!("  {}", item.text))); }
+                            let mut lines = vec![Line::from(heading)];
+                            match section.kind {
+                                feed::Kind::Featured => lines.extend(textwrap::wrap(&item.detail, width).into_iter().take(4).map(|l| Line::styled(format!("    {}", l), dim))),
+                                _ if !item.detail.is_empty() => lines[0].spans.push(Span::styled(format!("  ({})", item.detail), dim)),
+                                _ => {}
+                            }
+                            lines
+                        }
+                    };
+                    entry(&mut items, lines);
+                }
+            }
+        }
+    }
+
+    app.home_list_state.select(Some(selected_item));
+    f.render_stateful_widget(List::new(items).block(block), area, &mut app.home_list_state);
+}
+
+fn render_summary_preview<F>(f: &mut Frame, app: &mut App, area: Rect, border: F)
+where F: Fn(&str) -> Block<'static>
+{
+    let preview_block = border("Preview");
+    let inner = preview_block.inner(area);
+    f.render_widget(preview_block, area);
+
+    let Some(result) = app.search_results.get(app.selected_index) else { return; };
+    let dim = Style::default().fg(Color::DarkGray);
+
+    let summary = match app.summaries.get(&result.title) {
+        Some(SummaryState::Ready(summary)) => summary,
+        Some(SummaryState::Failed) => {
+            f.render_widget(Paragraph::new("No preview available.").style(dim), inner);
+            return;
+        }
+        _ => {
+            f.render_widget(Paragraph::new("[Loading Preview...]").alignment(Alignment::Center).style(dim), inner);
+            return;
+        }
+    };
+
+    let mut header = vec![Line::from(Span::styled(result.title.clone(), Style::default().fg(app.theme).add_modifier(Modifier::BOLD)))];
+    if !summary.description.is_empty() {
+        header.push(Line::from(Span::styled(summary.description.clone(), Style::default().add_modifier(Modifier::ITALIC))));
+    }
+
+    let thumb_height = if summary.thumbnail.is_some() { inner.height / 3 } else { 0 };
+    let chunks = Layout::vertical([
+        Constraint::Length(header.len() as u16 + 1),
+        Constraint::Length(thumb_height),
+        Constraint::Min(0),
+        Constraint::Length(1),
+    ]).split(inner);
+
+    f.render_widget(Paragraph::new(header), chunks[0]);
+
+    if let Some(url) = &summary.thumbnail {
+        if let Some(protocol) = app.thumbnail_protocols.get_mut(url) {
+            f.render_stateful_widget(StatefulImage::default(), chunks[1], protocol);
+        } else {
+            f.render_widget(Paragraph::new("[Loading Image...]").alignment(Alignment::Center).style(dim), chunks[1]);
+        }
+    }
+
+    f.render_widget(Paragraph::new(summary.extract.clone()).wrap(Wrap { trim: true }), chunks[2]);
+    f.render_widget(Paragraph::new(result.snippet.clone()).style(dim), chunks[3]);
+}
+
+fn render_references<F>(f: &mut Frame, app: &mut App, area: Rect, border: F)
+where F: Fn(&str) -> Block<'static>
+{
+    let popup = Rect::new(
+        area.x + area.width / 8,
+        area.y + area.height / 8,
+        area.width - area.width / 4,
+        area.height - area.height / 4,
+    );
+    let block = border("References").title_bottom(Span::styled(" Enter/o: Open Source  Esc: Close ", Style::default().fg(Color::DarkGray)));
+    let inner = block.inner(popup);
+    f.render_widget(Clear, popup);
+    f.render_widget(block, popup);
+
+    let width = inner.width.saturating_sub(2) as usize;
+    let items: Vec<ListItem> = app.references.iter().map(|r| {
+        let prefix = format!("{}. ", r.label);
+        let indent = " ".repeat(prefix.chars().count());
+        let mut lines: Vec<Line> = textwrap::wrap(&r.text, width.saturating_sub(prefix.len()).max(10)).into_iter().enumerate()
+            .map(|(i, w)| Line::from(format!("{}{}", if i == 0 { &prefix } else { &indent }, w)))
+            .collect();
+        if let Some(url) = &r.url {
+            lines.push(Line::from(Span::styled(format!("{}{}", indent, url), Style::default().fg(Color::DarkGray))));
+        }
+        ListItem::new(lines)
+    }).collect();
+    let list = List::new(items).highlight_style(Style::default().fg(app.theme).add_modifier(Modifier::BOLD));
+    f.render_stateful_widget(list, inner, &mut app.references_state);
+}
+
+fn render_image_view<F>(f: &mut Frame, app: &mut App, area: Rect, border: F)
+where F: Fn(&str) -> Block<'static>
+{
+    let count = app.figure_blocks().len();
+    let Some(figure) = app.current_figure() else { return; };
+    let info = match figure.file.as_ref().and_then(|file| app.image_info.get(file)) {
+        Some(ImageInfoState::Ready(info)) => Some(info.clone()),
+        _ => None,
+    };
+    let loading = matches!(figure.file.as_ref().and_then(|file| app.image_info.get(file)), Some(ImageInfoState::Loading));
+    let title = figure.file.clone().unwrap_or_else(|| "Image".to_string());
+    let thumb_url = figure.url.clone();
+
+    let label = Style::default().fg(app.theme).add_modifier(Modifier::BOLD);
+    let mut details = Vec::new();
+    let description = figure.caption.clone().or_else(|| info.as_ref().map(|i| i.description.clone())).filter(|d| !d.is_empty());
+    if let Some(description) = description {
+        details.push(Line::from(Span::styled(description, Style::default().add_modifier(Modifier::ITALIC))));
+    }
+    if let Some(info) = &info {
+        for (name, value) in [("Author", &info.author), ("License", &info.license)] {
+            if !value.is_empty() {
+                details.push(Line::from(vec![Span::styled(format!("{}: ", name), label), Span::raw(value.clone())]));
+            }
+        }
+        let mut size = format!("{} × {}", info.width, info.height);
+        if info.shown_width < info.width { size.push_str(&format!(" (shown {} px wide)", info.shown_width)); }
+        details.push(Line::from(vec![Span::styled("Size: ", label), Span::raw(size)]));
+    } else if loading {
+        details.push(Line::styled("Loading file information...", Style::default().fg(Color::DarkGray)));
+    }
+
+    let block = border(&format!("{} ({}/{})", title, app.image_view + 1, count))
+        .title_bottom(Span::styled(" h/l: Previous/Next  Enter: File Page  Esc: Close ", Style::default().fg(Color::DarkGray)));
+    let inner = block.inner(area);
+    f.render_widget(block, area);
+
+    let details_height = details.iter()
+        .map(|l| l.width().div_ceil(inner.width.max(1) as usize).max(1) as u16)
+        .sum::<u16>()
+        .min(inner.height / 3);
+    let [image_area, _, details_area] = Layout::vertical([Constraint::Min(0), Constraint::Length(1), Constraint::Length(details_height)]).areas(inner);
+
+    let full_url = info.map(|i| i.url);
+    let url = full_url.filter(|u| app.image_protocols.contains_key(u)).unwrap_or(thumb_url);
+    if let Some(protocol) = app.image_protocols.get_mut(&url) {
+        f.render_stateful_widget(StatefulImage::default(), image_area, protocol);
+    } else {
+        f.render_widget(Paragraph::new(app.image_status(&url)).alignment(Alignment::Center).style(Style::default().fg(Color::DarkGray)), image_area);
+    }
+    f.render_widget(Paragraph::new(details).wrap(Wrap { trim: true }), details_area);
+}
+
+struct ArticleLayout {
+    width: u16,
+    theme: Color,
+    inline_images: bool,
+    rows: Vec<Row>,
+    chapter_rows: Vec<usize>,
+}
+
+struct Row {
+    block: usize,
+    line: usize,
+    content: RowContent,
+}
+
+enum RowContent {
+    Line(Line<'static>),
+    TableLine(usize),
+    CodeLine(usize),
+    ImageLine(usize),
+}
+
+const SPINNER: [char; 10] = ['⠋', '⠙', '⠹', '⠸', '⠼', '⠴', '⠦', '⠧', '⠇', '⠏'];
+
+const INLINE_IMAGE_MAX_ROWS: u16 = 16;
+const INLINE_IMAGE_MAX_COLS: u16 = 60;
+const CURSOR_BG: Color = Color::Indexed(236);
+const SELECTION_STYLE: Style = Style::new().bg(Color::Indexed(238));
+const HIGHLIGHT_STYLE: Style = Style::new().bg(Color::Indexed(58));
+const SIDEBAR_WIDTH: u16 = 40;
+
+fn inline_image_size(figure: &Figure, width: u16) -> (u16, u16) {
+    let cols = width.min(INLINE_IMAGE_MAX_COLS);
+    if figure.width == 0 || figure.height == 0 { return (cols, 10); }
+    let aspect = figure.height as f32 / figure.width as f32;
+    let rows = (cols as f32 * aspect / 2.0).round() as u16;
+    if rows > INLINE_IMAGE_MAX_ROWS {
+        ((INLINE_IMAGE_MAX_ROWS as f32 * 2.0 / aspect).round() as u16, INLINE_IMAGE_MAX_ROWS)
+    } else {
+        (cols, rows.max(4))
+    }
+}
+
+fn layout_article(blocks: &[ContentBlock], width: u16, theme: Color, inline_images: bool) -> ArticleLayout {
+    let mut rows = Vec::new();
+    let mut chapter_rows = Vec::new();
+
+    for (block, content) in blocks.iter().enumerate() {
+        let mut push = |content: RowContent| rows.push(Row { block, line: 0, content });
+        match content {
+            ContentBlock::Text(lines) => {
+                for (i, line) in lines.iter().enumerate() {
+                    if let LineKind::Header(_) = line.kind {
+                        chapter_rows.push(rows.len());
+                    }
+                    for wrapped in text::wrap_line(line, width as usize, theme) {
+                        rows.push(Row { block, line: i, content: RowContent::Line(wrapped) });
+                    }
+                    if let LineKind::Header(_) = line.kind {
+                        rows.push(Row { block, line: i, content: RowContent::Line(Line::default()) });
+                    }
+                }
+            }
+            ContentBlock::Image(figure) if inline_images => {
+                let (_, height) = inline_image_size(figure, width);
+                for idx in 0..height as usize {
+                    push(RowContent::ImageLine(idx));
+                }
+                if let Some(caption) = &figure.caption {
+                    for line in textwrap::wrap(caption, (width as usize).saturating_sub(4).max(1)) {
+                        push(RowContent::Line(Line::from(vec![Span::raw("  "), Span::styled(line.into_owned(), Style::default().fg(Color::DarkGray).add_modifier(Modifier::ITALIC))])));
+                    }
+                }
+                push(RowContent::Line(Line::default()));
+            }
+            ContentBlock::Image(_) => {}
+            ContentBlock::Table(table) => {
+                let hint = if table.width > width as usize { "  ◂ h/l ▸  t: full screen" } else { "  t: full screen" };
+                push(RowContent::Line(Line::from(vec![
+                    Span::styled(table.caption.clone().unwrap_or_else(|| "Table".to_string()), Style::default().add_modifier(Modifier::ITALIC)),
+                    Span::styled(hint, Style::default().fg(Color::DarkGray)),
+                ])));
+                for idx in 0..table.lines.len() {
+                    push(RowContent::TableLine(idx));
+                }
+                push(RowContent::Line(Line::default()));
+            }
+            ContentBlock::Code(code) => {
+                for idx in 0..code.lines.len() {
+                    push(RowContent::CodeLine(idx));
+                }
+                push(RowContent::Line(Line::default()));
+            }
+            ContentBlock::Math { tex, unicode } => {
+                let (text, style) = match unicode {
+                    Some(text) => (text.clone(), Style::default()),
+                    None => (tex.clone(), Style::default().fg(Color::Cyan)),
+                };
+                for line in textwrap::wrap(&text, (width as usize).saturating_sub(4).max(1)) {
+                    let pad = (width as usize).saturating_sub(line.width()) / 2;
+                    push(RowContent::Line(Line::from(vec![Span::raw(" ".repeat(pad)), Span::styled(line.into_owned(), style)])));
+                }
+                push(RowContent::Line(Line::default()));
+            }
+        }
+    }
+
+    ArticleLayout { width, theme, inline_images, rows, chapter_rows }
+}
+
+fn row_text(row: &Row, blocks: &[ContentBlock]) -> String {
+    match (&row.content, &blocks[row.block]) {
+        (RowContent::Line(line), _) => line.to_string(),
+        (RowContent::TableLine(idx), ContentBlock::Table(table)) => table::styled_line(&table.lines[*idx], 0, table.width, Color::Reset).to_string(),
+        (RowContent::CodeLine(idx), ContentBlock::Code(code)) => text::styled_line(&code.lines[*idx], 0, code.width).to_string(),
+        _ => String::new(),
+    }
+}
+
+fn row_indent(row: &Row, blocks: &[ContentBlock]) -> usize {
+    match (&row.content, &blocks[row.block]) {
+        (RowContent::Line(_), ContentBlock::Text(lines)) => text::indent_width(&lines[row.line].kind),
+        _ => 0,
+    }
+}
+
+fn unit_offset(layout: &ArticleLayout, blocks: &[ContentBlock], i: usize) -> usize {
+    let row = &layout.rows[i];
+    layout.rows[..i].iter().rev()
+        .take_while(|r| (r.block, r.line) == (row.block, row.line))
+        .map(|r| row_text(r, blocks).chars().count().saturating_sub(row_indent(r, blocks)) + 1)
+        .sum()
+}
+
+fn layout_units(layout: &ArticleLayout, blocks: &[ContentBlock]) -> Vec<((usize, usize), String)> {
+    let mut units: Vec<((usize, usize), String)> = Vec::new();
+    for row in &layout.rows {
+        if !matches!((&row.content, &blocks[row.block]), (RowContent::Line(_), ContentBlock::Text(_))) { continue; }
+        let text: String = row_text(row, blocks).chars().skip(row_indent(row, blocks)).collect();
+        match units.last_mut() {
+            Some((unit, joined)) if *unit == (row.block, row.line) => { joined.push(' '); joined.push_str(&text); }
+            _ => units.push(((row.block, row.line), text)),
+        }
+    }
+    units
+}
+
+fn locate_highlight(units: &[((usize, usize), String)], highlight: &Highlight) -> Option<Mark> {
+    if highlight.quote.is_empty() { return None; }
+    let quote_len = highlight.quote.chars().count();
+    units.iter()
+        .flat_map(|((block, line), text)| text.match_indices(&highlight.quote).map(move |(i, _)| {
+            let before = text[..i].chars().rev().zip(highlight.before.chars().rev()).take_while(|(a, b)| a == b).count();
+            let after = text[i + highlight.quote.len()..].chars().zip(highlight.after.chars()).take_while(|(a, b)| a == b).count();
+            let start = text[..i].chars().count();
+            (before + after, (*block, *line, start, start + quote_len))
+        }))
+        .fold(None, |best: Option<(usize, Mark)>, found| match best {
+            Some(best) if best.0 >= found.0 => Some(best),
+            _ => Some(found),
+        })
+        .map(|(_, mark)| mark)
+}
+
+fn patch_range(mut line: Line<'static>, from: usize, to: usize, style: Style) -> Line<'static> {
+    let mut pos = 0;
+    let mut spans = Vec::new();
+    for span in std::mem::take(&mut line.spans) {
+        let chars: Vec<char> = span.content.chars().collect();
+        let (a, b) = (from.clamp(pos, pos + chars.len()) - pos, to.clamp(pos, pos + chars.len()) - pos);
+        pos += chars.len();
+        if a == b {
+            spans.push(span);
+            continue;
+        }
+        for (range, span_style) in [(0..a, span.style), (a..b, span.style.patch(style)), (b..chars.len(), span.style)] {
+            if !range.is_empty() { spans.push(Span::styled(chars[range].iter().collect::<String>(), span_style)); }
+        }
+    }
+    line.spans = spans;
+    line
 }
 
 fn render_reading_view<F>(f: &mut Frame, app: &mut App, area: Rect, border: F) 
 where F: Fn(&str) -> Block<'static>
 {
     let (content_area, side_area) = if !app.chapters.is_empty() {
-        let c = Layout::horizontal([Constraint::Min(40), Constraint::Length(40)]).split(area);
+        let c = Layout::horizontal([Constraint::Min(40), Constraint::Length(SIDEBAR_WIDTH)]).split(area);
         (c[0], Some(c[1]))
     } else { (area, None) };
 
-    let inner_content = border(&app.current_article_title).inner(content_area);
-    f.render_widget(border(&app.current_article_title), content_area);
-    
-    let content_width = inner_content.width.saturating_sub(1) as usize;
-    let mut y_draw = 0;
-    let mut current_scroll_row = 0;
-    let max_height = inner_content.height;
+    let mut article_block = border(&app.current_article_title);
+    if let Some(from) = &app.redirected_from {
+        article_block = article_block.title_bottom(Span::styled(format!(" Redirected from {} ", from), Style::default().fg(Color::DarkGray)));
+    }
+    let inner_content = article_block.inner(content_area);
+    f.render_widget(article_block, content_area);
     
-    let mut active_image_url = None;
-    let mut found_active = false;
-
-    for (i, block) in app.content_blocks.iter().enumerate() {
-        if y_draw >= max_height { break; }
-
-        match block {
-            ContentBlock::Text(raw_text) => {
-                for line_str in raw_text.lines() {
-                    if line_str.starts_with("###HEADER###") {
-                        let header_text = &line_str[12..]; 
-                        if current_scroll_row >= app.scroll_offset as usize {
-                            if y_draw < max_height {
-                                f.render_widget(
-                                    Paragraph::new(Span::styled(header_text, Style::default().fg(app.theme).add_modifier(Modifier::BOLD))),
-                                    Rect::new(inner_content.x, inner_content.y + y_draw, inner_content.width, 1)
-                                );
-                                y_draw += 1;
-                            }
-                        }
-                        current_scroll_row += 1;
-                        if current_scroll_row >= app.scroll_offset as usize {
-                             if y_draw < max_height { y_draw += 1; }
-                        }
-                        current_scroll_row += 1;
-                        continue;
-                    }
+    let content_width = inner_content.width.saturating_sub(1);
+    let stale = app.layout.as_ref().is_none_or(|l| l.width != content_width || l.theme != app.theme || l.inline_images != app.inline_images);
+    if stale {
+        let layout = layout_article(&app.content_blocks, content_width, app.theme, app.inline_images);
+        let units = layout_units(&layout, &app.content_blocks);
+        app.highlight_marks = app.highlights.iter().filter_map(|h| locate_highlight(&units, h)).collect();
+        app.layout = Some(layout);
+    }
+    let Some(layout) = &app.layout else { return; };
 
-                    let wrapped = textwrap::wrap(line_str, content_width);
-                    for w in wrapped {
-                        if current_scroll_row >= app.scroll_offset as usize {
-                            if y_draw < max_height {
-                                f.render_widget(Paragraph::new(w.into_owned()), Rect::new(inner_content.x, inner_content.y + y_draw, inner_content.width, 1));
-                                y_draw += 1;
-                            }
-                            if !found_active && y_draw > 0 && y_draw < 15 { 
-                                if i + 1 < app.content_blocks.len() {
-                                    if let ContentBlock::Image(url) = &app.content_blocks[i+1] {
-                                        active_image_url = Some(url.clone());
-                                        found_active = true;
-                                    }
-                                }
-                                if !found_active && i > 0 {
-                                    if let ContentBlock::Image(url) = &app.content_blocks[i-1] {
-                                        active_image_url = Some(url.clone());
-                                        found_active = true;
-                                    }
-                                }
-                            }
-                        }
-                        current_scroll_row += 1;
+    if let Some(i) = app.pending_chapter.take()
+        && let Some(row) = layout.chapter_rows.get(i)
+    {
+        app.scroll_offset = *row as u16;
+        app.cursor = *row;
+    }
+    let height = inner_content.height as usize;
+    app.viewport_height = height;
+    app.cursor = app.cursor.min(layout.rows.len().saturating_sub(1));
+    if app.cursor < app.scroll_offset as usize {
+        app.scroll_offset = app.cursor as u16;
+    } else if app.cursor >= app.scroll_offset as usize + height {
+        app.scroll_offset = (app.cursor + 1 - height) as u16;
+    }
+    app.scroll_offset = app.scroll_offset.min(layout.rows.len().saturating_sub(1) as u16);
+
+    let mut active_image = None;
+    let mut inline_images = Vec::new();
+    app.viewport_blocks.clear();
+
+    let selection = app.selection();
+    let visible = layout.rows.iter().skip(app.scroll_offset as usize).take(inner_content.height as usize);
+    for (y_draw, row) in visible.enumerate() {
+        let index = app.scroll_offset as usize + y_draw;
+        let mut line = match &row.content {
+            RowContent::Line(line) => line.clone(),
+            RowContent::TableLine(idx) => match &app.content_blocks[row.block] {
+                ContentBlock::Table(table) => table::styled_line(&table.lines[*idx], app.h_scroll, inner_content.width as usize, app.theme),
+                _ => Line::default(),
+            },
+            RowContent::CodeLine(idx) => match &app.content_blocks[row.block] {
+                ContentBlock::Code(code) => text::styled_line(&code.lines[*idx], app.h_scroll, inner_content.width as usize),
+                _ => Line::default(),
+            },
+            RowContent::ImageLine(idx) => match &app.content_blocks[row.block] {
+                ContentBlock::Image(figure) => {
+                    let (cols, rows) = inline_image_size(figure, content_width);
+                    if *idx == 0 && y_draw + rows as usize <= inner_content.height as usize {
+                        let x = inner_content.x + content_width.saturating_sub(cols) / 2;
+                        inline_images.push((figure.url.clone(), Rect::new(x, inner_content.y + y_draw as u16, cols, rows)));
+                    }
+                    if *idx == rows as usize / 2 {
+                        let status = if app.image_protocols.contains_key(&figure.url) { "[image]".to_string() } else { app.image_status(&figure.url) };
+                        Line::styled(status, Style::default().fg(Color::DarkGray)).alignment(Alignment::Center)
+                    } else {
+                        Line::default()
                     }
                 }
+                _ => Line::default(),
             },
-            ContentBlock::Image(_) => {}
+        };
+        if !app.viewport_blocks.contains(&row.block) { app.viewport_blocks.push(row.block); }
+        let mut row_style = if index == app.cursor { Style::default().bg(CURSOR_BG) } else { Style::default() };
+        if let RowContent::Line(_) = row.content
+            && app.highlight_marks.iter().any(|h| (h.0, h.1) == (row.block, row.line))
+        {
+            let indent = row_indent(row, &app.content_blocks);
+            let offset = unit_offset(layout, &app.content_blocks, index);
+            for &(_, _, start, end) in app.highlight_marks.iter().filter(|h| (h.0, h.1) == (row.block, row.line)) {
+                line = patch_range(line, indent + start.saturating_sub(offset), indent + end.saturating_sub(offset), HIGHLIGHT_STYLE);
+            }
+        }
+        if let Some((mode, (r1, c1), (r2, c2))) = selection
+            && (r1..=r2).contains(&index)
+        {
+            let text_row = matches!(row.content, RowContent::Line(_));
+            if text_row {
+                let from = if index == r1 { c1 } else { 0 };
+                let to = if index == r2 { c2 + 1 } else { usize::MAX };
+                line = patch_range(line, from, to, SELECTION_STYLE);
+            }
+            if mode == VisualMode::Line || !text_row { row_style = SELECTION_STYLE; }
+        }
+        if app.visual.is_some() && index == app.cursor {
+            if line.to_string().chars().count() <= app.cursor_col { line.spans.push(Span::raw(" ")); }
+            line = patch_range(line, app.cursor_col, app.cursor_col + 1, Style::default().add_modifier(Modifier::REVERSED));
+        }
+        f.render_widget(Paragraph::new(line).style(row_style), Rect::new(inner_content.x, inner_content.y + y_draw as u16, inner_content.width, 1));
+
+        if !app.inline_images && active_image.is_none() && y_draw < 14 && matches!(app.content_blocks[row.block], ContentBlock::Text(_)) {
+            let i = row.block;
+            if let Some(ContentBlock::Image(_)) = app.content_blocks.get(i + 1) {
+                active_image = Some(i + 1);
+            } else if i > 0
+                && let ContentBlock::Image(_) = &app.content_blocks[i - 1]
+            {
+                active_image = Some(i - 1);
+            }
+        }
+    }
+
+    for (url, rect) in inline_images {
+        if let Some(protocol) = app.image_protocols.get_mut(&url) {
+            f.render_widget(Clear, rect);
+            f.render_stateful_widget(StatefulImage::default(), rect, protocol);
         }
     }
 
     if let Some(s_area) = side_area {
-        let s_chunks = Layout::vertical([Constraint::Percentage(50), Constraint::Percentage(50)]).split(s_area);
-        
-        let ctx_block = border("Context");
-        let ctx_inner = ctx_block.inner(s_chunks[0]);
-        f.render_widget(ctx_block, s_chunks[0]);
+        let s_chunks = if app.inline_images {
+            Layout::vertical([Constraint::Length(0), Constraint::Min(0)]).split(s_area)
+        } else {
+            Layout::vertical([Constraint::Percentage(50), Constraint::Percentage(50)]).split(s_area)
+        };
 
-        if let Some(url) = active_image_url {
-            if let Some(protocol) = app.image_protocols.get_mut(&url) {
-                f.render_stateful_widget(StatefulImage::default(), ctx_inner, protocol);
-            } else {
-                f.render_widget(Paragraph::new("[Loading Image...]").alignment(Alignment::Center).style(Style::default().fg(Color::DarkGray)), ctx_inner);
+        if !app.inline_images {
+            let ctx_block = border("Context");
+            let ctx_inner = ctx_block.inner(s_chunks[0]);
+            f.render_widget(ctx_block, s_chunks[0]);
+
+            if let Some(ContentBlock::Image(figure)) = active_image.and_then(|i| app.content_blocks.get(i)) {
+                let caption_lines = figure.caption.as_deref()
+                    .map(|c| textwrap::wrap(c, ctx_inner.width.max(1) as usize).len().min(3) as u16)
+                    .unwrap_or(0);
+                let [image_area, caption_area] = Layout::vertical([Constraint::Min(0), Constraint::Length(caption_lines)]).areas(ctx_inner);
+                if let Some(protocol) = app.image_protocols.get_mut(&figure.url) {
+                    f.render_stateful_widget(StatefulImage::default(), image_area, protocol);
+                } else {
+                    f.render_widget(Paragraph::new(app.image_status(&figure.url)).alignment(Alignment::Center).style(Style::default().fg(Color::DarkGray)), image_area);
+                }
+                if let Some(caption) = &figure.caption {
+                    let style = Style::default().fg(Color::DarkGray).add_modifier(Modifier::ITALIC);
+                    f.render_widget(Paragraph::new(caption.as_str()).style(style).wrap(Wrap { trim: true }), caption_area);
+                }
             }
-        } 
+        }
         
         let is_chapters_focused = matches!(app.state, AppState::Chapters);
         let chap_color = if is_chapters_focused { Color::Cyan } else { app.theme };
@@ -801,40 +2785,376 @@ where F: Fn(&str) -> Block<'static>
     }
 }
 
+fn reading_list(path: &std::path::Path) -> Result<Vec<String>> {
+    let content = std::fs::read_to_string(path).map_err(|e| anyhow!("could not read {}: {}", path.display(), e))?;
+    Ok(content.lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty() && !line.starts_with('#'))
+        .map(|line| match line.rsplit_once("/wiki/") {
+            Some((_, title)) => percent_decode(title).replace('_', " "),
+            None => line.to_string(),
+        })
+        .collect())
+}
+
+async fn fetch_listed(client: &reqwest::Client, args: &cli::Args, site: &Site, title: String) -> Result<Option<NetworkEvent>> {
+    match fetch_article(client, site, title.clone()).await {
+        Ok(event) => Ok(Some(event)),
+        Err(e) if args.list.is_some() => {
+            eprintln!("rwiki: skipping {}: {}", title, e);
+            Ok(None)
+        }
+        Err(e) => Err(e),
+    }
+}
+
+async fn print_article(args: &cli::Args, site: &Site) -> Result<()> {
+    let client = http_client();
+    let titles = match (&args.title, &args.list) {
+        (_, Some(list)) => reading_list(list)?,
+        (Some(title), None) => vec![title.clone()],
+        (None, None) if args.random => vec![fetch_random(&client, site).await?],
+        (None, None) => return Err(anyhow!("an article title is required to print")),
+    };
+    let format = args.format.unwrap_or(cli::Format::Text);
+    if format == cli::Format::Epub {
+        return export_epub(&client, args, site, titles).await;
+    }
+    let width = args.width
+        .or_else(|| io::stdout().is_terminal().then(|| crossterm::terminal::size().ok().map(|(w, _)| w)).flatten())
+        .unwrap_or(80);
+
+    let mut texts = Vec::new();
+    for title in titles {
+        if let Some(event) = fetch_listed(&client, args, site, title).await? {
+            texts.push(output::article_output(event, format, site, width.max(20), args.section.as_deref())?);
+        }
+    }
+    let text = match format {
+        cli::Format::Json if args.list.is_some() => format!("[\n{}\n]\n", texts.join(",\n")),
+        cli::Format::Json => texts.join("\n") + "\n",
+        _ => texts.join("\n"),
+    };
+    if let Some(path) = &args.output {
+        return std::fs::write(path, text).map_err(|e| anyhow!("could not write {}: {}", path.display(), e));
+    }
+    match io::stdout().lock().write_all(text.as_bytes()) {
+        Err(e) if e.kind() != io::ErrorKind::BrokenPipe => Err(e.into()),
+        _ => Ok(()),
+    }
+}
+
+async fn export_epub(client: &reqwest::Client, args: &cli::Args, site: &Site, titles: Vec<String>) -> Result<()> {
+    let path = args.output.as_ref().ok_or_else(|| anyhow!("epub output needs a file, pass --output PATH"))?;
+    let mut articles = Vec::new();
+    for title in titles {
+        match fetch_listed(client, args, site, title.clone()).await? {
+            Some(NetworkEvent::ArticleLoaded { title, infobox, blocks, references, .. }) => {
+                articles.push(epub::Article::new(site, &title, &infobox, &blocks, &references));
+            }
+            Some(NetworkEvent::DisambiguationLoaded { .. }) => eprintln!("rwiki: skipping {}, it is a disambiguation page", title),
+            _ => {}
+        }
+    }
+    let book_title = match (&args.list, articles.as_slice()) {
+        (Some(list), _) => list.file_stem().map(|s| s.to_string_lossy().into_owned()).unwrap_or_else(|| "Reading list".to_string()),
+        (None, [article]) => article.title().to_string(),
+        _ => "Reading list".to_string(),
+    };
+    let limit = Arc::new(Semaphore::new(images::MAX_CONCURRENT_DOWNLOADS));
+    let progress = |done, total| if io::stderr().is_terminal() {
+        eprint!("\rrwiki: downloading images {}/{}{}", done, total, if done == total { "\n" } else { "" });
+    };
+    epub::write(client, site, &book_title, &articles, path, limit, progress).await
+}
+
 #[tokio::main]
 async fn main() -> Result<()> {
+    let args = cli::Args::parse();
+    if let Some(path) = &args.config {
+        let _ = CONFIG_PATH.set(path.clone());
+    }
+    let mut site = Site::from_args(&args);
+    if args.site.is_some() {
+        site.load_article_path(&http_client()).await;
+    }
+    if args.print || args.format.is_some() || args.output.is_some() || args.list.is_some() || (!io::stdout().is_terminal() && (args.title.is_some() || args.random)) {
+        return print_article(&args, &site).await;
+    }
+
+    restore_on_panic();
     enable_raw_mode()?;
     let mut stdout = io::stdout();
-    execute!(stdout, EnterAlternateScreen)?; 
+    execute!(stdout, EnterAlternateScreen, EnableMouseCapture)?;
+    TUI_ACTIVE.store(true, Ordering::SeqCst);
     let backend = CrosstermBackend::new(stdout);
     let mut terminal = Terminal::new(backend)?;
 
     let (action_tx, action_rx) = mpsc::unbounded_channel();
     let (event_tx, mut event_rx) = mpsc::unbounded_channel();
     
-    let mut app = App::new(action_tx);
-    tokio::spawn(run_network_loop(action_rx, event_tx.clone()));
+    let picker = Picker::from_query_stdio().unwrap_or_else(|_| Picker::from_fontsize((8, 12)));
+    let mut app = App::new(site.clone(), picker.clone(), action_tx, event_tx.clone());
+    tokio::spawn(run_network_loop(site, picker, action_rx, event_tx.clone()));
+    if let Some(title) = args.title {
+        let target = match &args.section {
+            Some(section) => format!("{}#{}", title, section),
+            None => title,
+        };
+        app.navigate(Action::FetchArticle(target));
+    } else if let Some(query) = args.search {
+        app.input = query.clone();
+        app.navigate(Action::Search(query));
+    } else if args.random {
+        app.navigate(Action::FetchRandom);
+    }
+    app.send(Action::FetchFeed);
+    #[cfg(unix)]
+    tokio::spawn(run_signal_listener(event_tx.clone()));
     tokio::spawn(run_config_watcher(event_tx));
 
+    let result = run_app(&mut terminal, &mut app, &mut event_rx);
+    suspend_terminal(&mut terminal)?;
+    match app.terminated {
+        Some(signal) => Err(anyhow!("terminated by {}", signal)),
+        None => result,
+    }
+}
+
+fn run_app(terminal: &mut Terminal<CrosstermBackend<io::Stdout>>, app: &mut App, event_rx: &mut mpsc::UnboundedReceiver<(u64, NetworkEvent)>) -> Result<()> {
     let tick_rate = Duration::from_millis(100);
     let mut last_tick = std::time::Instant::now();
 
     loop {
-        terminal.draw(|f| ui(f, &mut app))?;
+        terminal.draw(|f| ui(f, app))?;
         if crossterm::event::poll(tick_rate.checked_sub(last_tick.elapsed()).unwrap_or(Duration::from_secs(0)))? {
             match event::read()? {
-                Event::Key(key) => {
-                    if key.kind == KeyEventKind::Press { if app.handle_key(key.code) { break; } }
+                Event::Key(key) if key.kind == KeyEventKind::Press => match key.code {
+                    KeyCode::Char('c') if key.modifiers.contains(KeyModifiers::CONTROL) => return Ok(()),
+                    KeyCode::Char('z') if key.modifiers.contains(KeyModifiers::CONTROL) => app.external = Some(External::Stop),
+                    code => if app.handle_key(code) { return Ok(()); },
                 },
+                Event::Mouse(mouse) => app.handle_mouse(mouse),
                 _ => {}
             }
         }
+        if let Some(external) = app.external.take() {
+            suspend_terminal(terminal)?;
+            app.run_external(external);
+            resume_terminal(terminal)?;
+        }
         while let Ok(e) = event_rx.try_recv() { app.on_tick(Some(e)); }
+        if app.terminated.is_some() { return Ok(()); }
         if last_tick.elapsed() >= tick_rate { app.on_tick(None); last_tick = std::time::Instant::now(); }
     }
+}
 
+fn suspend_terminal(terminal: &mut Terminal<CrosstermBackend<io::Stdout>>) -> Result<()> {
+    TUI_ACTIVE.store(false, Ordering::SeqCst);
     disable_raw_mode()?;
-    execute!(terminal.backend_mut(), LeaveAlternateScreen)?;
+    execute!(terminal.backend_mut(), DisableMouseCapture, LeaveAlternateScreen)?;
     terminal.show_cursor()?;
     Ok(())
 }
+
+fn resume_terminal(terminal: &mut Terminal<CrosstermBackend<io::Stdout>>) -> Result<()> {
+    enable_raw_mode()?;
+    execute!(terminal.backend_mut(), EnterAlternateScreen, EnableMouseCapture)?;
+    TUI_ACTIVE.store(true, Ordering::SeqCst);
+    terminal.clear()?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn test_app() -> (App, mpsc::UnboundedReceiver<(u64, Action)>) {
+        let (action_tx, action_rx) = mpsc::unbounded_channel();
+        let (event_tx, _) = mpsc::unbounded_channel();
+        let site = Site::from_args(&cli::Args::parse_from(["rwiki"]));
+        (App::new(site, Picker::from_fontsize((8, 12)), action_tx, event_tx), action_rx)
+    }
+
+    #[tokio::test]
+    async fn image_events_for_an_older_article_are_dropped() {
+        let (mut app, _actions) = test_app();
+        app.article_generation = 2;
+        app.generation = 3;
+        let image = |app: &App| app.image_picker.new_resize_protocol(DynamicImage::new_rgb8(1, 1));
+        app.on_tick(Some((1, NetworkEvent::ArticleImageDownloaded("old.png".to_string(), image(&app)))));
+        app.on_tick(Some((1, NetworkEvent::ImageInfoLoaded("Old.png".to_string(), None))));
+        app.on_tick(Some((2, NetworkEvent::ArticleImageDownloaded("new.png".to_string(), image(&app)))));
+        app.on_tick(Some((2, NetworkEvent::ImageInfoLoaded("New.png".to_string(), None))));
+        assert!(!app.image_protocols.contains_key("old.png"));
+        assert!(app.image_protocols.contains_key("new.png"));
+        assert!(!app.image_info.contains_key("Old.png"));
+        assert!(matches!(app.image_info.get("New.png"), Some(ImageInfoState::Failed)));
+    }
+
+    #[test]
+    fn searching_from_the_reader_keeps_the_article_requests() {
+        let (mut app, mut actions) = test_app();
+        (app.generation, app.article_generation) = (2, 2);
+        app.navigate(Action::Search("rust".to_string()));
+        assert!(matches!(actions.try_recv(), Ok((3, Action::Cancel(keep))) if keep.contains(&2)));
+        assert!(matches!(actions.try_recv(), Ok((3, Action::Search(_)))));
+        app.send(Action::DownloadImage("figure.png".to_string()));
+        assert!(matches!(actions.try_recv(), Ok((2, Action::DownloadImage(_)))));
+        app.cancel_loading();
+        assert!(matches!(actions.try_recv(), Ok((4, Action::Cancel(keep))) if keep.contains(&2)));
+    }
+
+    const CITED_HTML: &str = concat!(
+        "<p>Rust is fast<sup id=\"cite_ref-1\" class=\"reference\"><a href=\"#cite_note-1\">[1]</a></sup>",
+        "<sup id=\"cite_ref-a\" class=\"reference\"><a href=\"#cite_note-a\">[note 2]</a></sup> and mc<sup>2</sup> is not a citation.</p>",
+        "<ol class=\"references\">",
+        "<li id=\"cite_note-1\"><span class=\"reference-text\">The <a rel=\"nofollow\" class=\"external text\" href=\"//example.org/a?x=1&amp;y=2\">Rust book</a>.</span></li>",
+        "<li id=\"cite_note-a\"><span class=\"reference-text\">A   note without a link.</span></li>",
+        "</ol>",
+    );
+
+    #[test]
+    fn citation_markers_become_cite_links() {
+        let (html, _) = extract_references(CITED_HTML);
+        assert!(html.contains("<a href=\"#cite:1\">¹</a>˒<a href=\"#cite:note 2\">ⁿᵒᵗᵉ ²</a>"));
+        assert!(html.contains("mc<sup>2</sup>"));
+        assert!(!html.contains("<ol class=\"references\""));
+    }
+
+    #[test]
+    fn references_keep_their_labels_and_external_urls() {
+        let (_, references) = extract_references(CITED_HTML);
+        let labels: Vec<_> = references.iter().map(|r| r.label.as_str()).collect();
+        assert_eq!(labels, ["1", "note 2"]);
+        assert_eq!(references[0].text, "The Rust book.");
+        assert_eq!(references[0].url.as_deref(), Some("https://example.org/a?x=1&y=2"));
+        assert_eq!(references[1].text, "A note without a link.");
+        assert_eq!(references[1].url, None);
+    }
+
+    #[test]
+    fn the_reference_under_the_cursor_is_opened() {
+        let (mut app, _actions) = test_app();
+        let (html, references) = extract_references(CITED_HTML);
+        app.content_blocks = vec![ContentBlock::Text(text::rich_lines(&html))];
+        app.references = references;
+        app.layout = Some(layout_article(&app.content_blocks, 80, Color::Reset, false));
+        assert_eq!(app.citation_at_cursor(), Some(0));
+        app.visual = Some((VisualMode::Char, (0, 0)));
+        app.cursor_col = "Rust is fast¹˒".chars().count();
+        assert_eq!(app.citation_at_cursor(), Some(1));
+    }
+
+    fn highlight(before: &str, quote: &str, after: &str) -> Highlight {
+        Highlight { quote: quote.to_string(), before: before.to_string(), after: after.to_string() }
+    }
+
+    #[test]
+    fn highlights_are_found_by_quote_and_context() {
+        let units = vec![
+            ((0, 0), "The cat sat on the mat.".to_string()),
+            ((2, 1), "Later the cat sat on the sofa.".to_string()),
+        ];
+        assert_eq!(locate_highlight(&units, &highlight("Later the ", "cat sat", " on the sofa")), Some((2, 1, 10, 17)));
+        assert_eq!(locate_highlight(&units, &highlight("The ", "cat sat", " on the mat")), Some((0, 0, 4, 11)));
+        assert_eq!(locate_highlight(&units, &highlight("", "dog", "")), None);
+    }
+
+    #[test]
+    fn civil_date_counts_days_from_the_epoch() {
+        assert_eq!(civil_date(0), (1970, 1, 1));
+        assert_eq!(civil_date(-1), (1969, 12, 31));
+        assert_eq!(civil_date(11016), (2000, 2, 29));
+        assert_eq!(civil_date(11017), (2000, 3, 1));
+        assert_eq!(civil_date(19723), (2024, 1, 1));
+        assert_eq!(civil_date(-25508), (1900, 3, 1));
+    }
+
+    #[test]
+    fn disambiguation_entries_keep_their_descriptions_and_skip_see_also() {
+        let site = Site::from_args(&cli::Args::parse_from(["rwiki"]));
+        let html = concat!(
+            "<p><b>Mercury</b> may refer to:</p>",
+            "<ul><li><a href=\"/wiki/Mercury_(planet)\" title=\"Mercury (planet)\">Mercury (planet)</a>, the closest planet to the Sun</li>",
+            "<li><a href=\"/wiki/Mercury_(element)\" title=\"Mercury (element)\">Mercury</a> (element), a <i>chemical</i> element</li>",
+            "<li><a href=\"/w/index.php?title=Mercury_(band)&amp;action=edit&amp;redlink=1\" class=\"new\" title=\"Mercury (band)\">Mercury (band)</a></li>",
+            "<li><a href=\"/wiki/File:Mercury.png\" title=\"File:Mercury.png\">image</a></li></ul>",
+            "<div class=\"mw-heading mw-heading2\"><h2 id=\"See_also\">See also</h2></div>",
+            "<ul><li><a href=\"/wiki/Quicksilver\" title=\"Quicksilver\">Quicksilver</a></li></ul>",
+        );
+        let entries: Vec<_> = parse_disambiguation(&site, html).into_iter().map(|e| (e.title, e.description)).collect();
+        assert_eq!(entries, [
+            ("Mercury (planet)".to_string(), "Mercury (planet), the closest planet to the Sun".to_string()),
+            ("Mercury (element)".to_string(), "Mercury (element), a chemical element".to_string()),
+        ]);
+    }
+
+    #[test]
+    fn fragments_match_headings_in_any_anchor_encoding() {
+        assert!(fragment_matches("History_of_Rust", "History of Rust"));
+        assert!(fragment_matches("history_of_rust", " History of Rust "));
+        assert!(fragment_matches("1990%E2%80%932000", "1990–2000"));
+        assert!(fragment_matches("1990.E2.80.932000", "1990–2000"));
+        assert!(!fragment_matches("History", "History of Rust"));
+    }
+
+    const THUMB: &str = "//upload.wikimedia.org/wikipedia/commons/thumb/a/ab/Caf%C3%A9_map.jpg/250px-Caf%C3%A9_map.jpg";
+
+    fn figures(placeholders: Vec<Option<ContentBlock>>) -> Vec<(String, Option<String>, Option<String>, u32)> {
+        placeholders.into_iter().map(|block| match block {
+            Some(ContentBlock::Image(figure)) => (figure.url, figure.file, figure.caption, figure.width),
+            _ => panic!("expected an image placeholder"),
+        }).collect()
+    }
+
+    #[test]
+    fn figures_keep_their_file_and_caption() {
+        let html = format!(concat!(
+            "<p>Before</p>",
+            "<figure typeof=\"mw:File/Thumb\"><a href=\"/wiki/File:Caf%C3%A9_map.jpg\"><img src=\"{0}\" width=\"250\" height=\"180\" data-file-width=\"1000\"></a>",
+            "<figcaption>A <b>map</b> of the café</figcaption></figure>",
+            "<div class=\"thumb tright\"><div class=\"thumbinner\"><img src=\"{0}\" width=\"220\" height=\"160\">",
+            "<div class=\"thumbcaption\"><div class=\"magnify\"></div>Old style</div></div></div>",
+            "<figure><img src=\"//upload.wikimedia.org/wikipedia/commons/a/ab/Icon.png\" width=\"20\" height=\"20\"></figure>",
+            "<p>After</p>",
+        ), THUMB);
+        let mut placeholders = Vec::new();
+        let out = extract_figures(&html, &mut placeholders);
+        assert_eq!(out, "<p>Before</p><p>###BLOCK###0</p><p>###BLOCK###1</p><figure><img src=\"//upload.wikimedia.org/wikipedia/commons/a/ab/Icon.png\" width=\"20\" height=\"20\"></figure><p>After</p>");
+        let url = format!("https:{}", THUMB);
+        let file = Some("Café map.jpg".to_string());
+        assert_eq!(figures(placeholders), [
+            (url.clone(), file.clone(), Some("A map of the café".to_string()), 250),
+            (url, file, Some("Old style".to_string()), 220),
+        ]);
+    }
+
+    #[test]
+    fn figure_images_need_an_absolute_non_math_source() {
+        let original = figure_image("src=\"https://upload.wikimedia.org/wikipedia/commons/a/ab/Plan.png\" width=\"400\" height=\"300\" data-file-width=\"400\"", None).unwrap();
+        assert_eq!((original.url.as_str(), original.file.as_deref(), original.file_width), ("https://upload.wikimedia.org/wikipedia/commons/a/ab/Plan.png", Some("Plan.png"), 400));
+        assert!(figure_image("src=\"static/images/icon.png\" width=\"400\"", None).is_none());
+        assert!(figure_image("src=\"https://wikimedia.org/api/rest_v1/media/math/render/svg/abc\" width=\"400\"", None).is_none());
+        assert_eq!(figure_image("src=\"https://example.org/logo.png\"", None).unwrap().file, None);
+    }
+
+    #[test]
+    fn gallery_items_become_images_with_their_captions() {
+        let html = format!(concat!(
+            "<p>Photos</p><ul class=\"gallery mw-gallery-traditional\">",
+            "<li class=\"gallerybox\" style=\"width: 155px\"><div class=\"thumb\"><span typeof=\"mw:File\"><a href=\"/wiki/File:Caf%C3%A9_map.jpg\"><img src=\"{}\" width=\"120\" height=\"90\"></a></span></div>",
+            "<div class=\"gallerytext\">The <i>old</i> town</div></li>",
+            "<li class=\"gallerybox\"><div class=\"thumb\"><img src=\"//upload.wikimedia.org/wikipedia/commons/b/bc/River.jpg\" width=\"120\" height=\"80\"></div><div class=\"gallerytext\"></div></li>",
+            "<li class=\"gallerybox\"><div class=\"thumb\">Missing file</div></li>",
+            "</ul><p>After</p>",
+        ), THUMB);
+        let mut placeholders = Vec::new();
+        let out = extract_galleries(&html, &mut placeholders);
+        assert_eq!(out, "<p>Photos</p><p>###BLOCK###0</p><p>###BLOCK###1</p><p>After</p>");
+        assert_eq!(figures(placeholders), [
+            (format!("https:{}", THUMB), Some("Café map.jpg".to_string()), Some("The old town".to_string()), 120),
+            ("https://upload.wikimedia.org/wikipedia/commons/b/bc/River.jpg".to_string(), Some("River.jpg".to_string()), None, 120),
+        ]);
+    }
+}
@@ -0,0 +1,165 @@
+use crate::{site::Site, text::{InlineStyle, LineKind, TextLine}, ContentBlock, Reference};
+
+fn escape(text: &str) -> String {
+    let mut out = String::with_capacity(text.len());
+    for c in text.chars() {
+        if matches!(c, '\\' | '*' | '_' | '`' | '[' | ']' | '<' | '>' | '|') { out.push('\\'); }
+        out.push(c);
+    }
+    out
+}
+
+fn inline(spans: &[(String, InlineStyle)], site: &Site, title: &str) -> String {
+    let mut out = String::new();
+    for (text, style) in spans {
+        let trimmed = text.trim();
+        if trimmed.is_empty() {
+            out.push_str(text);
+            continue;
+        }
+        let leading = &text[..text.len() - text.trim_start().len()];
+        let trailing = &text[text.trim_end().len()..];
+        let mut md = if style.code { format!("`{}`", trimmed.replace('`', "'")) } else { escape(trimmed) };
+        if style.italic { md = format!("*{}*", md); }
+        if style.bold { md = format!("**{}**", md); }
+        if style.strike { md = format!("~~{}~~", md); }
+        if let Some(url) = style.link.as_deref().and_then(|href| site.link_url(title, href)) {
+            md = format!("[{}]({})", md, url.replace('(', "%28").replace(')', "%29"));
+        }
+        out.push_str(leading);
+        out.push_str(&md);
+        out.push_str(trailing);
+    }
+    out.trim().to_string()
+}
+
+fn separate(out: &mut String) {
+    if !out.is_empty() && !out.ends_with("\n\n") {
+        out.push('\n');
+    }
+}
+
+fn table_row(cells: &[String]) -> String {
+    let cells: Vec<String> = cells.iter().map(|c| escape(c)).collect();
+    format!("| {} |\n", cells.join(" | "))
+}
+
+fn write_lines(out: &mut String, lines: &[TextLine], site: &Site, title: &str, previous: &mut Option<LineKind>) {
+    for line in lines {
+        let text = inline(&line.spans, site, title);
+        let continues = matches!((previous.as_ref(), &line.kind),
+            (Some(LineKind::ListItem { .. }), LineKind::ListItem { .. }) | (Some(LineKind::Quote(_)), LineKind::Quote(_)));
+        if !continues { separate(out); }
+        match &line.kind {
+            LineKind::Paragraph => out.push_str(&text),
+            LineKind::Header(level) => out.push_str(&format!("{} {}", "#".repeat(*level), text)),
+            LineKind::ListItem { indent, marker } => {
+                let marker = match marker.as_str() {
+                    "" => "  ".to_string(),
+                    "•" => "- ".to_string(),
+                    m => format!("{} ", m),
+                };
+                out.push_str(&format!("{}{}{}", " ".repeat(*indent), marker, text));
+            }
+            LineKind::Quote(depth) => out.push_str(&format!("{}{}", "> ".repeat(*depth), text)),
+        }
+        out.push('\n');
+        *previous = Some(line.kind.clone());
+    }
+}
+
+fn write_block(out: &mut String, block: &ContentBlock) {
+    separate(out);
+    match block {
+        ContentBlock::Text(_) => {}
+        ContentBlock::Image(figure) => {
+            let caption = figure.caption.as_deref().or(figure.file.as_deref()).unwrap_or("");
+            out.push_str(&format!("![{}]({})\n", escape(caption), figure.url));
+            if let Some(caption) = &figure.caption {
+                out.push_str(&format!("\n*{}*\n", escape(caption)));
+            }
+        }
+        ContentBlock::Table(table) => {
+            if let Some(caption) = &table.caption {
+                out.push_str(&format!("**{}**\n\n", escape(caption)));
+            }
+            let Some((header, rows)) = table.grid.split_first() else { return; };
+            out.push_str(&table_row(header));
+            out.push_str(&format!("|{}\n", " --- |".repeat(header.len())));
+            for row in rows {
+                out.push_str(&table_row(row));
+            }
+        }
+        ContentBlock::Code(code) => {
+            out.push_str(&format!("```{}\n{}\n```\n", code.lang.as_deref().unwrap_or(""), code.source));
+        }
+        ContentBlock::Math { tex, .. } => out.push_str(&format!("$$\n{}\n$$\n", tex)),
+    }
+}
+
+pub fn article_markdown(site: &Site, title: &str, infobox: &str, blocks: &[ContentBlock], references: &[Reference], section: bool) -> String {
+    let mut out = format!("# {}\n\nSource: <{}>\n", escape(title), site.page_url(title));
+    let mut previous = None;
+
+    if !section && !infobox.is_empty() {
+        separate(&mut out);
+        for line in infobox.lines().map(str::trim) {
+            match line.is_empty() {
+                true => out.push_str(">\n"),
+                false => out.push_str(&format!("> {}  \n", escape(line))),
+            }
+        }
+    }
+    for block in blocks {
+        match block {
+            ContentBlock::Text(lines) => write_lines(&mut out, lines, site, title, &mut previous),
+            block => { write_block(&mut out, block); previous = None; }
+        }
+    }
+    if !section && !references.is_empty() {
+        separate(&mut out);
+        out.push_str("## References\n\n");
+        for reference in references {
+            out.push_str(&format!("- \\[{}\\] {}", reference.label, escape(&reference.text)));
+            if let Some(url) = &reference.url {
+                out.push_str(&format!(" <{}>", url));
+            }
+            out.push('\n');
+        }
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use clap::Parser;
+    use crate::{cli::Args, table::Table, text::rich_lines};
+
+    fn markdown(blocks: &[ContentBlock]) -> String {
+        let site = Site::from_args(&Args::parse_from(["rwiki"]));
+        article_markdown(&site, "A*b", "", blocks, &[], true)
+    }
+
+    #[test]
+    fn markup_characters_are_escaped() {
+        let out = markdown(&[ContentBlock::Text(rich_lines("<p>2*3 = snake_case [1] and <b>bold_word</b> <a href=\"/wiki/C_(language)\">C</a></p>"))]);
+        assert_eq!(out, "# A\\*b\n\nSource: <https://en.wikipedia.org/wiki/A*b>\n\n2\\*3 = snake\\_case \\[1\\] and **bold\\_word** [C](https://en.wikipedia.org/wiki/C_%28language%29)\n");
+    }
+
+    #[test]
+    fn tables_and_math_become_markdown_blocks() {
+        let table = Table {
+            caption: Some("Sizes".to_string()),
+            lines: Vec::new(),
+            width: 0,
+            grid: vec![vec!["Name".to_string(), "Value".to_string()], vec!["a|b".to_string(), "*1*".to_string()]],
+        };
+        let out = markdown(&[ContentBlock::Table(table), ContentBlock::Math { tex: "\\frac{a}{b}".to_string(), unicode: Some("a/b".to_string()) }]);
+        assert_eq!(out, concat!(
+            "# A\\*b\n\nSource: <https://en.wikipedia.org/wiki/A*b>\n\n",
+            "**Sizes**\n\n| Name | Value |\n| --- | --- |\n| a\\|b | \\*1\\* |\n\n",
+            "$$\n\\frac{a}{b}\n$$\n",
+        ));
+    }
+}
@@ -0,0 +1,350 @@
+use crate::{attr, decode_entities};
+
+#[derive(Clone, Debug, PartialEq)]
+enum Token {
+    Command(String),
+    Char(char),
+    Open,
+    Close,
+    Sup,
+    Sub,
+}
+
+fn tokenize(tex: &str) -> Vec<Token> {
+    let mut tokens = Vec::new();
+    let mut chars = tex.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            '\\' => {
+                let mut name = String::new();
+                while let Some(&n) = chars.peek() {
+                    if !n.is_ascii_alphabetic() { break; }
+                    name.push(n);
+                    chars.next();
+                }
+                if name.is_empty()
+                    && let Some(n) = chars.next()
+                {
+                    name.push(n);
+                }
+                tokens.push(Token::Command(name));
+            }
+            '{' => tokens.push(Token::Open),
+            '}' => tokens.push(Token::Close),
+            '^' => tokens.push(Token::Sup),
+            '_' => tokens.push(Token::Sub),
+            c => tokens.push(Token::Char(c)),
+        }
+    }
+    tokens
+}
+
+fn symbol(name: &str) -> Option<&'static str> {
+    Some(match name {
+        "alpha" => "α", "beta" => "β", "gamma" => "γ", "delta" => "δ", "epsilon" => "ϵ", "varepsilon" => "ε",
+        "zeta" => "ζ", "eta" => "η", "theta" => "θ", "vartheta" => "ϑ", "iota" => "ι", "kappa" => "κ",
+        "lambda" => "λ", "mu" => "μ", "nu" => "ν", "xi" => "ξ", "pi" => "π", "varpi" => "ϖ", "rho" => "ρ",
+        "varrho" => "ϱ", "sigma" => "σ", "varsigma" => "ς", "tau" => "τ", "upsilon" => "υ", "phi" => "ϕ",
+        "varphi" => "φ", "chi" => "χ", "psi" => "ψ", "omega" => "ω",
+        "Gamma" => "Γ", "Delta" => "Δ", "Theta" => "Θ", "Lambda" => "Λ", "Xi" => "Ξ", "Pi" => "Π",
+        "Sigma" => "Σ", "Upsilon" => "Υ", "Phi" => "Φ", "Psi" => "Ψ", "Omega" => "Ω",
+        "times" => "×", "cdot" => "⋅", "div" => "÷", "pm" => "±", "mp" => "∓", "ast" => "∗", "star" => "⋆",
+        "leq" | "le" => "≤", "geq" | "ge" => "≥", "neq" | "ne" => "≠", "approx" => "≈", "equiv" => "≡",
+        "sim" => "∼", "simeq" => "≃", "cong" => "≅", "propto" => "∝", "ll" => "≪", "gg" => "≫",
+        "infty" => "∞", "partial" => "∂", "nabla" => "∇", "sum" => "∑", "prod" => "∏", "coprod" => "∐",
+        "int" => "∫", "iint" => "∬", "iiint" => "∭", "oint" => "∮",
+        "to" | "rightarrow" => "→", "leftarrow" | "gets" => "←", "leftrightarrow" => "↔", "mapsto" => "↦",
+        "Rightarrow" | "implies" => "⇒", "Leftarrow" => "⇐", "Leftrightarrow" | "iff" => "⇔",
+        "uparrow" => "↑", "downarrow" => "↓", "longrightarrow" => "⟶", "longmapsto" => "⟼",
+        "in" => "∈", "notin" => "∉", "ni" => "∋", "subset" => "⊂", "supset" => "⊃", "subseteq" => "⊆",
+        "supseteq" => "⊇", "cup" => "∪", "cap" => "∩", "setminus" => "∖", "emptyset" | "varnothing" => "∅",
+        "forall" => "∀", "exists" => "∃", "neg" | "lnot" => "¬", "land" | "wedge" => "∧", "lor" | "vee" => "∨",
+        "oplus" => "⊕", "otimes" => "⊗", "circ" => "∘", "bullet" => "∙", "perp" => "⊥", "parallel" => "∥",
+        "angle" => "∠", "triangle" => "△", "degree" => "°", "prime" => "′",
+        "ldots" | "dots" | "dotsc" => "…", "cdots" | "dotsb" => "⋯", "vdots" => "⋮", "ddots" => "⋱",
+        "hbar" => "ℏ", "ell" => "ℓ", "Re" => "ℜ", "Im" => "ℑ", "aleph" => "ℵ", "wp" => "℘",
+        "langle" => "⟨", "rangle" => "⟩", "lfloor" => "⌊", "rfloor" => "⌋", "lceil" => "⌈", "rceil" => "⌉",
+        "mid" | "vert" => "|", "Vert" | "|" => "‖", "backslash" => "\\",
+        "{" => "{", "}" => "}", "%" => "%", "$" => "$", "&" => "&", "#" => "#", "_" => "_",
+        "," | ":" | ";" | " " | "quad" => " ", "qquad" => "  ", "!" => "",
+        "\\" => "; ",
+        "sin" => "sin", "cos" => "cos", "tan" => "tan", "cot" => "cot", "sec" => "sec", "csc" => "csc",
+        "arcsin" => "arcsin", "arccos" => "arccos", "arctan" => "arctan", "sinh" => "sinh", "cosh" => "cosh",
+        "tanh" => "tanh", "log" => "log", "ln" => "ln", "lg" => "lg", "exp" => "exp", "lim" => "lim",
+        "max" => "max", "min" => "min", "sup" => "sup", "inf" => "inf", "det" => "det", "deg" => "deg",
+        "dim" => "dim", "ker" => "ker", "gcd" => "gcd", "arg" => "arg", "Pr" => "Pr", "mod" | "bmod" => "mod",
+        _ => return None,
+    })
+}
+
+const IGNORED: &[&str] = &[
+    "displaystyle", "textstyle", "scriptstyle", "left", "right", "big", "Big", "bigg", "Bigg",
+    "bigl", "bigr", "Bigl", "Bigr", "limits", "nolimits",
+];
+
+const TEXT_GROUPS: &[&str] = &[
+    "mathrm", "mathit", "mathbf", "mathsf", "mathtt", "text", "textrm", "textit", "textbf",
+    "operatorname", "boldsymbol", "mbox", "rm", "bf", "it",
+];
+
+pub fn superscript(c: char) -> Option<char> {
+    Some(match c {
+        '0' => '⁰', '1' => '¹', '2' => '²', '3' => '³', '4' => '⁴', '5' => '⁵', '6' => '⁶', '7' => '⁷',
+        '8' => '⁸', '9' => '⁹', '+' => '⁺', '-' | '−' => '⁻', '=' => '⁼', '(' => '⁽', ')' => '⁾',
+        'a' => 'ᵃ', 'b' => 'ᵇ', 'c' => 'ᶜ', 'd' => 'ᵈ', 'e' => 'ᵉ', 'f' => 'ᶠ', 'g' => 'ᵍ', 'h' => 'ʰ',
+        'i' => 'ⁱ', 'j' => 'ʲ', 'k' => 'ᵏ', 'l' => 'ˡ', 'm' => 'ᵐ', 'n' => 'ⁿ', 'o' => 'ᵒ', 'p' => 'ᵖ',
+        'r' => 'ʳ', 's' => 'ˢ', 't' => 'ᵗ', 'u' => 'ᵘ', 'v' => 'ᵛ', 'w' => 'ʷ', 'x' => 'ˣ', 'y' => 'ʸ',
+        'z' => 'ᶻ', 'T' => 'ᵀ', '′' => '′', ' ' => ' ',
+        _ => return None,
+    })
+}
+
+fn subscript(c: char) -> Option<char> {
+    Some(match c {
+        '0' => '₀', '1' => '₁', '2' => '₂', '3' => '₃', '4' => '₄', '5' => '₅', '6' => '₆', '7' => '₇',
+        '8' => '₈', '9' => '₉', '+' => '₊', '-' | '−' => '₋', '=' => '₌', '(' => '₍', ')' => '₎',
+        'a' => 'ₐ', 'e' => 'ₑ', 'h' => 'ₕ', 'i' => 'ᵢ', 'j' => 'ⱼ', 'k' => 'ₖ', 'l' => 'ₗ', 'm' => 'ₘ',
+        'n' => 'ₙ', 'o' => 'ₒ', 'p' => 'ₚ', 'r' => 'ᵣ', 's' => 'ₛ', 't' => 'ₜ', 'u' => 'ᵤ', 'v' => 'ᵥ',
+        'x' => 'ₓ', 'β' => 'ᵦ', 'γ' => 'ᵧ', 'ρ' => 'ᵨ', 'φ' | 'ϕ' => 'ᵩ', 'χ' => 'ᵪ', ' ' => ' ',
+        _ => return None,
+    })
+}
+
+fn blackboard(c: char) -> char {
+    match c {
+        'N' => 'ℕ', 'Z' => 'ℤ', 'Q' => 'ℚ', 'R' => 'ℝ', 'C' => 'ℂ', 'P' => 'ℙ', 'H' => 'ℍ',
+        other => other,
+    }
+}
+
+fn is_simple(s: &str) -> bool {
+    s.chars().count() <= 1 || s.chars().all(|c| c.is_alphanumeric() || c == '.')
+}
+
+struct Parser {
+    tokens: Vec<Token>,
+    pos: usize,
+    text: bool,
+}
+
+impl Parser {
+    fn skip_space(&mut self) {
+        while !self.text && matches!(self.tokens.get(self.pos), Some(Token::Char(c)) if c.is_whitespace()) {
+            self.pos += 1;
+        }
+    }
+
+    fn group(&mut self) -> Option<String> {
+        self.skip_space();
+        match self.tokens.get(self.pos)? {
+            Token::Open => {
+                self.pos += 1;
+                let inner = self.sequence(true)?;
+                self.pos += 1;
+                Some(inner)
+            }
+            _ => self.atom(),
+        }
+    }
+
+    fn optional_arg(&mut self) -> Option<String> {
+        if self.tokens.get(self.pos) != Some(&Token::Char('[')) { return None; }
+        let end = self.tokens[self.pos..].iter().position(|t| *t == Token::Char(']'))? + self.pos;
+        let mut inner = Parser { tokens: self.tokens[self.pos + 1..end].to_vec(), pos: 0, text: false };
+        self.pos = end + 1;
+        inner.sequence(false)
+    }
+
+    fn atom(&mut self) -> Option<String> {
+        let token = self.tokens.get(self.pos)?.clone();
+        self.pos += 1;
+        match token {
+            Token::Char(c) => Some(match c {
+                '-' => "−".to_string(),
+                '*' => "∗".to_string(),
+                '\'' => "′".to_string(),
+                '~' => " ".to_string(),
+                '&' => " ".to_string(),
+                c => c.to_string(),
+            }),
+            Token::Command(name) => self.command(&name),
+            Token::Open => {
+                let inner = self.sequence(true)?;
+                self.pos += 1;
+                Some(inner)
+            }
+            Token::Close | Token::Sup | Token::Sub => None,
+        }
+    }
+
+    fn command(&mut self, name: &str) -> Option<String> {
+        if IGNORED.contains(&name) { return Some(String::new()); }
+        if TEXT_GROUPS.contains(&name) {
+            let text = std::mem::replace(&mut self.text, name.starts_with("text") || name == "mbox");
+            let body = self.group();
+            self.text = text;
+            return body;
+        }
+        if let Some(s) = symbol(name) {
+            self.skip_space();
+            let word = s.len() > 1 && s.chars().all(|c| c.is_ascii_alphabetic());
+            let next_is_word = matches!(self.tokens.get(self.pos), Some(Token::Char(c)) if c.is_alphanumeric())
+                || matches!(self.tokens.get(self.pos), Some(Token::Command(_)));
+            return Some(if word && next_is_word { format!("{} ", s) } else { s.to_string() });
+        }
+        match name {
+            "frac" | "tfrac" | "dfrac" | "cfrac" => {
+                let num = self.group()?;
+                let den = self.group()?;
+                Some(match (is_simple(&num), is_simple(&den)) {
+                    (true, true) => format!("{}/{}", num, den),
+                    (true, false) => format!("{}/({})", num, den),
+                    (false, true) => format!("({})/{}", num, den),
+                    (false, false) => format!("({})/({})", num, den),
+                })
+            }
+            "sqrt" => {
+                let root = self.optional_arg();
+                let body = self.group()?;
+                let sign = match root.as_deref() {
+                    None | Some("2") => "√".to_string(),
+                    Some("3") => "∛".to_string(),
+                    Some("4") => "∜".to_string(),
+                    Some(n) => format!("{}√", script(n, superscript).unwrap_or_else(|| format!("({})", n))),
+                };
+                Some(if is_simple(&body) { format!("{}{}", sign, body) } else { format!("{}({})", sign, body) })
+            }
+            "binom" => {
+                let n = self.group()?;
+                let k = self.group()?;
+                Some(format!("C({}, {})", n, k))
+            }
+            "mathbb" => Some(self.group()?.chars().map(blackboard).collect()),
+            "overline" | "bar" => Some(combine(&self.group()?, '\u{0305}')),
+            "hat" | "widehat" => Some(combine(&self.group()?, '\u{0302}')),
+            "tilde" | "widetilde" => Some(combine(&self.group()?, '\u{0303}')),
+            "vec" => Some(combine(&self.group()?, '\u{20D7}')),
+            "dot" => Some(combine(&self.group()?, '\u{0307}')),
+            "ddot" => Some(combine(&self.group()?, '\u{0308}')),
+            "underline" => Some(combine(&self.group()?, '\u{0332}')),
+            "begin" | "end" => {
+                self.group()?;
+                Some(if name == "begin" { String::new() } else { " ".to_string() })
+            }
+            _ => None,
+        }
+    }
+
+    fn sequence(&mut self, in_group: bool) -> Option<String> {
+        let mut out = String::new();
+        while let Some(token) = self.tokens.get(self.pos) {
+            match token {
+                Token::Close if in_group => break,
+                Token::Close => { self.pos += 1; }
+                Token::Char(c) if c.is_whitespace() && !self.text => { self.pos += 1; }
+                Token::Sup | Token::Sub => {
+                    let map = if *token == Token::Sup { superscript } else { subscript };
+                    let marker = if *token == Token::Sup { '^' } else { '_' };
+                    self.pos += 1;
+                    let body = self.group()?;
+                    match script(&body, map) {
+                        Some(s) => out.push_str(&s),
+                        None if body.chars().count() == 1 => { out.push(marker); out.push_str(&body); }
+                        None => out.push_str(&format!("{}({})", marker, body)),
+                    }
+                }
+                _ => out.push_str(&self.atom()?),
+            }
+        }
+        Some(out)
+    }
+}
+
+fn script(body: &str, map: fn(char) -> Option<char>) -> Option<String> {
+    body.chars().map(map).collect()
+}
+
+fn combine(body: &str, mark: char) -> String {
+    if body.chars().count() == 1 { format!("{}{}", body, mark) } else { body.chars().flat_map(|c| [c, mark]).collect() }
+}
+
+pub fn latex_to_unicode(tex: &str) -> Option<String> {
+    let mut parser = Parser { tokens: tokenize(tex), pos: 0, text: false };
+    let out = parser.sequence(false)?;
+    let out = out.split_whitespace().collect::<Vec<_>>().join(" ");
+    (!out.is_empty()).then_some(out)
+}
+
+pub fn strip_displaystyle(tex: &str) -> String {
+    let tex = tex.trim();
+    let inner = tex.strip_prefix('{').and_then(|t| t.strip_suffix('}')).unwrap_or(tex).trim();
+    inner.strip_prefix("\\displaystyle").unwrap_or(inner).trim().to_string()
+}
+
+pub struct MathElement {
+    pub tex: String,
+    pub display: bool,
+}
+
+pub fn parse_math_element(tag_content: &str, inner: &str) -> Option<MathElement> {
+    let tex = inner.find("<math").and_then(|i| {
+        let math_tag = &inner[i..i + inner[i..].find('>')?];
+        attr(math_tag, "alttext").map(decode_entities)
+    }).or_else(|| {
+        let i = inner.find("application/x-tex")?;
+        let rest = &inner[i..];
+        let start = rest.find('>')? + 1;
+        let end = rest.find("</annotation>")?;
+        Some(decode_entities(&rest[start..end]))
+    }).or_else(|| {
+        let i = inner.find("<img")?;
+        let img_tag = &inner[i..i + inner[i..].find('>')?];
+        attr(img_tag, "alt").map(decode_entities)
+    })?;
+
+    let display = tag_content.contains("mwe-math-element-block")
+        || inner.contains("mwe-math-mathml-display")
+        || inner.contains("mwe-math-fallback-image-display");
+    Some(MathElement { tex: strip_displaystyle(&tex), display })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn scripts_use_unicode_forms() {
+        assert_eq!(latex_to_unicode("E = mc^2").as_deref(), Some("E=mc²"));
+        assert_eq!(latex_to_unicode("x_{i+1}").as_deref(), Some("xᵢ₊₁"));
+        assert_eq!(latex_to_unicode("e^{i\\pi}").as_deref(), Some("e^(iπ)"));
+    }
+
+    #[test]
+    fn fractions_and_roots() {
+        assert_eq!(latex_to_unicode("\\frac{1}{2}").as_deref(), Some("1/2"));
+        assert_eq!(latex_to_unicode("\\frac{a+b}{c}").as_deref(), Some("(a+b)/c"));
+        assert_eq!(latex_to_unicode("\\sqrt{x}").as_deref(), Some("√x"));
+        assert_eq!(latex_to_unicode("\\sqrt[3]{x}").as_deref(), Some("∛x"));
+        assert_eq!(latex_to_unicode("\\sqrt[n]{x+1}").as_deref(), Some("ⁿ√(x+1)"));
+    }
+
+    #[test]
+    fn symbols_and_text() {
+        assert_eq!(latex_to_unicode("\\alpha \\leq \\beta").as_deref(), Some("α≤β"));
+        assert_eq!(latex_to_unicode("\\sin x").as_deref(), Some("sin x"));
+        assert_eq!(latex_to_unicode("\\mathbb{R}^n").as_deref(), Some("ℝⁿ"));
+        assert_eq!(latex_to_unicode("\\text{if } x > 0").as_deref(), Some("if x>0"));
+    }
+
+    #[test]
+    fn displaystyle_wrapper_is_stripped() {
+        assert_eq!(strip_displaystyle("{\\displaystyle x^2}"), "x^2");
+        assert_eq!(strip_displaystyle("x"), "x");
+    }
+
+    #[test]
+    fn unknown_commands_are_not_converted() {
+        assert_eq!(latex_to_unicode("\\unknowncommand{x}"), None);
+        assert_eq!(latex_to_unicode(""), None);
+    }
+}
@@ -0,0 +1,177 @@
+use anyhow::{anyhow, Result};
+use ratatui::style::Color;
+
+use crate::{cli::Format, fragment_matches, json, layout_article, ArticleLayout, markdown, site::Site, text::LineKind, ContentBlock, DisambiguationEntry, NetworkEvent, Reference, RowContent};
+
+fn headers(blocks: &[ContentBlock]) -> Vec<(usize, usize, usize)> {
+    blocks.iter().enumerate()
+        .filter_map(|(b, block)| match block {
+            ContentBlock::Text(lines) => Some(lines.iter().enumerate().map(move |(l, line)| (b, l, line))),
+            _ => None,
+        })
+        .flatten()
+        .filter_map(|(b, l, line)| match line.kind {
+            LineKind::Header(level) => Some((b, l, level)),
+            _ => None,
+        })
+        .collect()
+}
+
+fn section_range(blocks: &[ContentBlock], i: usize) -> Option<((usize, usize), (usize, usize))> {
+    let headers = headers(blocks);
+    let &(block, line, level) = headers.get(i)?;
+    let end = headers[i + 1..].iter()
+        .find(|(_, _, l)| *l <= level)
+        .map_or((blocks.len(), 0), |&(b, l, _)| (b, l));
+    Some(((block, line), end))
+}
+
+pub fn section_rows(layout: &ArticleLayout, blocks: &[ContentBlock], i: usize) -> (usize, usize) {
+    let Some((start, end)) = section_range(blocks, i) else { return (0, layout.rows.len()); };
+    let row = |position| layout.rows.partition_point(|r| (r.block, r.line) < position);
+    (row(start), row(end))
+}
+
+pub fn section_blocks(blocks: &[ContentBlock], name: &str) -> Option<Vec<ContentBlock>> {
+    let i = headers(blocks).iter().position(|&(b, l, _)| match &blocks[b] {
+        ContentBlock::Text(lines) => fragment_matches(name, &lines[l].plain()),
+        _ => false,
+    })?;
+    let ((start_block, start_line), (end_block, end_line)) = section_range(blocks, i)?;
+    let mut section = Vec::new();
+    for (b, block) in blocks.iter().enumerate().take(end_block + 1).skip(start_block) {
+        let from = if b == start_block { start_line } else { 0 };
+        match block {
+            ContentBlock::Text(lines) => {
+                let to = if b == end_block { end_line } else { lines.len() };
+                if from < to { section.push(ContentBlock::Text(lines[from..to].to_vec())); }
+            }
+            block if b < end_block => section.push(block.clone()),
+            _ => {}
+        }
+    }
+    Some(section)
+}
+
+fn disambiguation(title: &str, entries: &[DisambiguationEntry], format: Format, site: &Site) -> String {
+    let mut out = match format {
+        Format::Markdown => format!("# {}\n\n{} may refer to:\n\n", title, title),
+        _ => format!("{} may refer to:\n\n", title),
+    };
+    for entry in entries {
+        let name = match format {
+            Format::Markdown => format!("- [{}]({})", entry.title, site.page_url(&entry.title)),
+            _ => format!("  {}", entry.title),
+        };
+        match entry.description.is_empty() {
+            true => out.push_str(&format!("{}\n", name)),
+            false => out.push_str(&format!("{} - {}\n", name, entry.description)),
+        }
+    }
+    out
+}
+
+pub fn article_output(event: NetworkEvent, format: Format, site: &Site, width: u16, section: Option<&str>) -> Result<String> {
+    match event {
+        NetworkEvent::ArticleLoaded { title, infobox, infobox_fields, blocks, references, .. } => {
+            let blocks = match section {
+                Some(name) => section_blocks(&blocks, name).ok_or_else(|| anyhow!("no section named '{}' in {}", name, title))?,
+                None => blocks,
+            };
+            match format {
+                Format::Text => article_text(&title, &infobox, &blocks, &references, width, section.is_some()),
+                Format::Markdown => Ok(markdown::article_markdown(site, &title, &infobox, &blocks, &references, section.is_some())),
+                Format::Json => json::article_json(site, &title, &infobox_fields, &blocks, &references, section.is_some()),
+                Format::Epub => Err(anyhow!("epub can only be written to a file")),
+            }
+        }
+        NetworkEvent::DisambiguationLoaded { title, entries } if format == Format::Json => json::disambiguation_json(site, &title, &entries),
+        NetworkEvent::DisambiguationLoaded { title, entries } => Ok(disambiguation(&title, &entries, format, site)),
+        NetworkEvent::Error(msg) => Err(anyhow!(msg)),
+        _ => Err(anyhow!("unexpected response")),
+    }
+}
+
+pub fn article_text(title: &str, infobox: &str, blocks: &[ContentBlock], references: &[Reference], width: u16, section: bool) -> Result<String> {
+    let layout = layout_article(blocks, width, Color::Reset, false);
+
+    let mut out = format!("{}\n{}\n\n", title, "=".repeat(title.chars().count()));
+    if !section && !infobox.is_empty() {
+        out.push_str(infobox);
+        out.push_str("\n\n");
+    }
+    for row in &layout.rows {
+        let line = match &row.content {
+            RowContent::Line(line) => match &blocks[row.block] {
+                ContentBlock::Table(table) if !line.spans.is_empty() => table.caption.clone().unwrap_or_default(),
+                ContentBlock::Table(_) => String::new(),
+                _ => line.to_string(),
+            },
+            RowContent::TableLine(idx) => match &blocks[row.block] {
+                ContentBlock::Table(table) => table.lines[*idx].iter().map(|(text, _)| text.as_str()).collect(),
+                _ => String::new(),
+            },
+            RowContent::CodeLine(idx) => match &blocks[row.block] {
+                ContentBlock::Code(code) => code.lines[*idx].iter().map(|(text, _)| text.as_str()).collect(),
+                _ => String::new(),
+            },
+            RowContent::ImageLine(_) => continue,
+        };
+        out.push_str(line.trim_end());
+        out.push('\n');
+    }
+
+    if !section && !references.is_empty() {
+        out.push_str("\nReferences\n\n");
+        for reference in references {
+            out.push_str(&format!("[{}] {}", reference.label, reference.text));
+            if let Some(url) = &reference.url {
+                out.push_str(&format!(" <{}>", url));
+            }
+            out.push('\n');
+        }
+    }
+    Ok(out)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use clap::Parser;
+    use crate::{cli::Args, text::rich_lines};
+
+    fn blocks() -> Vec<ContentBlock> {
+        vec![
+            ContentBlock::Text(rich_lines("<p>Lead</p><h2>History</h2><p>Old</p><h3>Early years</h3><p>Older</p>")),
+            ContentBlock::Math { tex: "x^2".to_string(), unicode: None },
+            ContentBlock::Text(rich_lines("<h2>Legacy</h2><p>New</p>")),
+        ]
+    }
+
+    fn text(blocks: &[ContentBlock]) -> Vec<String> {
+        blocks.iter().flat_map(|block| match block {
+            ContentBlock::Text(lines) => lines.iter().map(|line| line.plain()).filter(|text| !text.is_empty()).collect(),
+            ContentBlock::Math { tex, .. } => vec![tex.clone()],
+            _ => Vec::new(),
+        }).collect()
+    }
+
+    #[test]
+    fn sections_include_their_subsections() {
+        assert_eq!(text(&section_blocks(&blocks(), "History").unwrap()), ["History", "Old", "Early years", "Older", "x^2"]);
+        assert_eq!(text(&section_blocks(&blocks(), "Early_years").unwrap()), ["Early years", "Older", "x^2"]);
+    }
+
+    #[test]
+    fn sections_match_without_case_and_report_missing_names() {
+        assert_eq!(text(&section_blocks(&blocks(), "LEGACY").unwrap()), ["Legacy", "New"]);
+        assert!(section_blocks(&blocks(), "Reception").is_none());
+        let event = NetworkEvent::ArticleLoaded {
+            title: "Test".to_string(), redirected_from: None, fragment: None, infobox: String::new(), infobox_fields: Vec::new(),
+            blocks: blocks(), chapters: Vec::new(), references: Vec::new(),
+        };
+        let site = Site::from_args(&Args::parse_from(["rwiki"]));
+        let error = article_output(event, Format::Text, &site, 80, Some("Reception")).unwrap_err();
+        assert_eq!(error.to_string(), "no section named 'Reception' in Test");
+    }
+}
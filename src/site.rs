@@ -0,0 +1,259 @@
+use std::path::{Path, PathBuf};
+use std::time::SystemTime;
+
+use crate::{cli::Args, percent_decode, Highlight};
+
+const ARTICLE_CACHE_LIMIT_BYTES: u64 = 256 * 1024 * 1024;
+
+#[derive(Clone, Debug)]
+pub struct Site {
+    pub base: String,
+    pub lang: String,
+    api: String,
+    article_path: String,
+    pub offline: bool,
+    cache_dir: Option<PathBuf>,
+    data_dir: Option<PathBuf>,
+}
+
+fn prune(dir: &Path, limit: u64) {
+    let Ok(entries) = std::fs::read_dir(dir) else { return; };
+    let mut files: Vec<(SystemTime, u64, PathBuf)> = entries
+        .filter_map(|entry| {
+            let entry = entry.ok()?;
+            let meta = entry.metadata().ok()?;
+            Some((meta.modified().ok()?, meta.len(), entry.path()))
+        })
+        .collect();
+    files.sort();
+    let mut total: u64 = files.iter().map(|(_, len, _)| len).sum();
+    for (_, len, path) in files {
+        if total <= limit { break; }
+        if std::fs::remove_file(path).is_ok() { total -= len; }
+    }
+}
+
+fn cache_key(title: &str) -> String {
+    let title = title.trim().replace(' ', "_");
+    let mut chars = title.chars();
+    let normalized: String = chars.next().map(|c| c.to_uppercase().chain(chars).collect()).unwrap_or_default();
+    normalized.bytes().map(|b| match b {
+        b'a'..=b'z' | b'A'..=b'Z' | b'0'..=b'9' | b'_' | b'-' | b'.' | b'(' | b')' | b',' => (b as char).to_string(),
+        b => format!("%{:02X}", b),
+    }).collect()
+}
+
+async fn fetch_article_path(client: &reqwest::Client, api: &str) -> Option<String> {
+    let params = [("action", "query"), ("meta", "siteinfo"), ("siprop", "general"), ("format", "json")];
+    let json = client.get(api).query(&params).send().await.ok()?.json::<serde_json::Value>().await.ok()?;
+    json.pointer("/query/general/articlepath").and_then(|v| v.as_str()).map(|s| s.to_string())
+}
+
+impl Site {
+    pub fn from_args(args: &Args) -> Self {
+        let base = match &args.site {
+            Some(site) if site.contains("://") => site.trim_end_matches('/').to_string(),
+            Some(site) => format!("https://{}", site.trim_end_matches('/')),
+            None => format!("https://{}.wikipedia.org", args.lang),
+        };
+        let (base, api) = match base.strip_suffix("api.php") {
+            Some(prefix) => {
+                let prefix = prefix.trim_end_matches('/');
+                (prefix.strip_suffix("/w").unwrap_or(prefix).to_string(), base.clone())
+            }
+            None => (base.clone(), format!("{}/w/api.php", base)),
+        };
+        let host = base.split("://").nth(1).unwrap_or(&base).replace(['/', ':'], "_");
+        let cache_dir = dirs::cache_dir().map(|dir| dir.join("rwiki").join(&host));
+        let data_dir = dirs::data_dir().map(|dir| dir.join("rwiki").join(&host));
+        Self { base, lang: args.lang.clone(), api, article_path: "/wiki/$1".to_string(), offline: args.offline, cache_dir, data_dir }
+    }
+
+    pub async fn load_article_path(&mut self, client: &reqwest::Client) {
+        let path = self.cache_dir.as_ref().map(|dir| dir.join("articlepath"));
+        let fetched = match self.offline {
+            true => None,
+            false => fetch_article_path(client, &self.api).await,
+        };
+        let article_path = match (fetched, &path) {
+            (Some(fetched), Some(path)) => {
+                if let Some(dir) = path.parent() {
+                    let _ = tokio::fs::create_dir_all(dir).await;
+                }
+                let _ = tokio::fs::write(path, &fetched).await;
+                Some(fetched)
+            }
+            (Some(fetched), None) => Some(fetched),
+            (None, Some(path)) => tokio::fs::read_to_string(path).await.ok(),
+            (None, None) => None,
+        };
+        if let Some(article_path) = article_path.filter(|p| p.contains("$1")) {
+            self.article_path = article_path;
+        }
+    }
+
+    pub fn api_url(&self) -> &str {
+        &self.api
+    }
+
+    pub fn rest_url(&self) -> String {
+        format!("{}/api/rest_v1", self.base)
+    }
+
+    pub fn page_url(&self, title: &str) -> String {
+        format!("{}{}", self.base, self.article_path.replace("$1", &title.replace(' ', "_")))
+    }
+
+    pub fn page_title(&self, href: &str) -> Option<String> {
+        let prefix = self.article_path.split("$1").next().unwrap_or("/wiki/");
+        let page = href.strip_prefix(prefix)?;
+        Some(percent_decode(page.split('#').next().unwrap_or(page)).replace('_', " "))
+    }
+
+    pub fn link_url(&self, title: &str, href: &str) -> Option<String> {
+        if href.contains("redlink=1") { return None; }
+        Some(match href {
+            h if h.starts_with("//") => format!("https:{}", h),
+            h if h.starts_with('/') => format!("{}{}", self.base, h),
+            h if h.starts_with('#') => format!("{}{}", self.page_url(title), h),
+            h => h.to_string(),
+        }.replace(' ', "%20"))
+    }
+
+    fn article_path(&self, title: &str) -> Option<PathBuf> {
+        Some(self.cache_dir.as_ref()?.join("articles").join(format!("{}.json", cache_key(title))))
+    }
+
+    pub async fn cached_article(&self, title: &str) -> Option<serde_json::Value> {
+        let path = self.article_path(title)?;
+        let content = tokio::fs::read_to_string(&path).await.ok()?;
+        if let Ok(file) = std::fs::File::options().append(true).open(&path) {
+            let _ = file.set_modified(SystemTime::now());
+        }
+        serde_json::from_str(&content).ok()
+    }
+
+    pub async fn store_article(&self, titles: &[&str], json: &serde_json::Value) {
+        let content = json.to_string();
+        for path in titles.iter().filter_map(|title| self.article_path(title)) {
+            if let Some(dir) = path.parent() {
+                let _ = tokio::fs::create_dir_all(dir).await;
+            }
+            let _ = tokio::fs::write(path, &content).await;
+        }
+        if let Some(dir) = self.cache_dir.as_ref().map(|d| d.join("articles")) {
+            let _ = tokio::task::spawn_blocking(move || prune(&dir, ARTICLE_CACHE_LIMIT_BYTES)).await;
+        }
+    }
+
+    fn feed_path(&self, date: &str) -> Option<PathBuf> {
+        Some(self.cache_dir.as_ref()?.join("feed").join(format!("{}.json", date)))
+    }
+
+    pub async fn cached_feed(&self, date: &str) -> Option<(serde_json::Value, std::time::SystemTime)> {
+        let path = self.feed_path(date)?;
+        let modified = tokio::fs::metadata(&path).await.and_then(|m| m.modified()).ok()?;
+        let json = serde_json::from_str(&tokio::fs::read_to_string(path).await.ok()?).ok()?;
+        Some((json, modified))
+    }
+
+    pub async fn store_feed(&self, date: &str, json: &serde_json::Value) {
+        let Some(path) = self.feed_path(date) else { return; };
+        if let Some(dir) = path.parent() {
+            let _ = tokio::fs::create_dir_all(dir).await;
+        }
+        let _ = tokio::fs::write(path, json.to_string()).await;
+    }
+
+    fn highlights_path(&self, title: &str) -> Option<PathBuf> {
+        Some(self.data_dir.as_ref()?.join("highlights").join(format!("{}.json", cache_key(title))))
+    }
+
+    pub fn highlights(&self, title: &str) -> Vec<Highlight> {
+        self.highlights_path(title)
+            .and_then(|path| std::fs::read_to_string(path).ok())
+            .and_then(|content| serde_json::from_str(&content).ok())
+            .unwrap_or_default()
+    }
+
+    pub fn store_highlights(&self, title: &str, highlights: &[Highlight]) {
+        let Some(path) = self.highlights_path(title) else { return; };
+        if highlights.is_empty() {
+            let _ = std::fs::remove_file(path);
+            return;
+        }
+        if let Some(dir) = path.parent() {
+            let _ = std::fs::create_dir_all(dir);
+        }
+        let _ = std::fs::write(path, serde_json::json!(highlights).to_string());
+    }
+
+    pub fn cached_titles(&self) -> Vec<String> {
+        let Some(dir) = self.cache_dir.as_ref().map(|d| d.join("articles")) else { return Vec::new(); };
+        let Ok(entries) = std::fs::read_dir(dir) else { return Vec::new(); };
+        let mut titles: Vec<String> = entries
+            .filter_map(|entry| entry.ok()?.file_name().to_str()?.strip_suffix(".json").map(|s| percent_decode(s).replace('_', " ")))
+            .collect();
+        titles.sort();
+        titles.dedup();
+        titles
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use clap::Parser;
+
+    fn site(args: &[&str]) -> Site {
+        Site::from_args(&Args::parse_from(std::iter::once("rwiki").chain(args.iter().copied())))
+    }
+
+    #[test]
+    fn site_urls_are_derived_from_the_arguments() {
+        let wikipedia = site(&["--lang", "de"]);
+        assert_eq!((wikipedia.base.as_str(), wikipedia.api_url()), ("https://de.wikipedia.org", "https://de.wikipedia.org/w/api.php"));
+        let api = site(&["--site", "https://wiki.archlinux.org/api.php"]);
+        assert_eq!((api.base.as_str(), api.api_url()), ("https://wiki.archlinux.org", "https://wiki.archlinux.org/api.php"));
+        let script_path = site(&["--site", "https://example.org/w/api.php"]);
+        assert_eq!((script_path.base.as_str(), script_path.api_url()), ("https://example.org", "https://example.org/w/api.php"));
+        let host = site(&["--site", "wiki.example.org/"]);
+        assert_eq!((host.base.as_str(), host.api_url()), ("https://wiki.example.org", "https://wiki.example.org/w/api.php"));
+        assert!(Args::try_parse_from(["rwiki", "--site", "wiki.example.org", "--lang", "de"]).is_err());
+    }
+
+    #[test]
+    fn page_urls_follow_the_article_path() {
+        let mut site = site(&["--site", "wiki.archlinux.org/api.php"]);
+        assert_eq!(site.page_url("Main page"), "https://wiki.archlinux.org/wiki/Main_page");
+        site.article_path = "/title/$1".to_string();
+        assert_eq!(site.page_url("Main page"), "https://wiki.archlinux.org/title/Main_page");
+        assert_eq!(site.page_title("/title/Caf%C3%A9_au_lait#History").as_deref(), Some("Café au lait"));
+        assert_eq!(site.page_title("/wiki/Main_page"), None);
+    }
+
+    #[test]
+    fn cache_keys_are_normalized_and_percent_encoded() {
+        assert_eq!(cache_key(" rust (programming language) "), "Rust_(programming_language)");
+        assert_eq!(cache_key("über"), "%C3%9Cber");
+        assert_eq!(cache_key("AC/DC?"), "AC%2FDC%3F");
+    }
+
+    #[test]
+    fn pruning_removes_the_oldest_articles_first() {
+        let dir = std::env::temp_dir().join(format!("rwiki-prune-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let now = SystemTime::now();
+        for (i, name) in ["old", "middle", "new"].iter().enumerate() {
+            let path = dir.join(name);
+            std::fs::write(&path, [0u8; 10]).unwrap();
+            let age = std::time::Duration::from_secs(60 * (3 - i as u64));
+            std::fs::File::options().append(true).open(&path).unwrap().set_modified(now - age).unwrap();
+        }
+        prune(&dir, 20);
+        let mut left: Vec<_> = std::fs::read_dir(&dir).unwrap().map(|e| e.unwrap().file_name().into_string().unwrap()).collect();
+        left.sort();
+        std::fs::remove_dir_all(&dir).unwrap();
+        assert_eq!(left, ["middle", "new"]);
+    }
+}
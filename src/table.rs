@@ -0,0 +1,305 @@
+use html2text::render::text_renderer::TrivialDecorator;
+use ratatui::prelude::*;
+use unicode_width::{UnicodeWidthChar, UnicodeWidthStr};
+
+use crate::{attr, element_end, text};
+
+const MAX_COLUMN_WIDTH: usize = 32;
+const WIDE_FILLER: char = '\0';
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum CellKind {
+    Border,
+    Data,
+    Header,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Align {
+    Left,
+    Center,
+    Right,
+}
+
+#[derive(Clone, Debug)]
+pub struct Cell {
+    pub text: String,
+    pub header: bool,
+    pub align: Align,
+    pub colspan: usize,
+    pub rowspan: usize,
+}
+
+#[derive(Clone, Debug)]
+pub struct Table {
+    pub caption: Option<String>,
+    pub lines: Vec<Vec<(String, CellKind)>>,
+    pub width: usize,
+    pub grid: Vec<Vec<String>>,
+}
+
+pub fn plain_text(html: &str) -> String {
+    let text = html2text::from_read_with_decorator(format!("<p>{}</p>", html).as_bytes(), 10000, TrivialDecorator::new());
+    text.split_whitespace().collect::<Vec<_>>().join(" ")
+}
+
+fn children<'a>(html: &'a str, tags: &[&'static str]) -> Vec<(&'static str, &'a str, &'a str)> {
+    let mut found = Vec::new();
+    let mut pos = 0;
+    while pos < html.len() {
+        let next = tags.iter()
+            .filter_map(|tag| {
+                html[pos..].match_indices(&format!("<{}", tag))
+                    .map(|(i, _)| pos + i)
+                    .find(|&i| html[i + tag.len() + 1..].starts_with(|c: char| c.is_whitespace() || c == '>'))
+                    .map(|i| (i, *tag))
+            })
+            .min_by_key(|(i, _)| *i);
+        let Some((start, tag)) = next else { break; };
+        let Some(open_end) = html[start..].find('>').map(|e| start + e) else { break; };
+        let Some(end) = element_end(html, start, tag) else { break; };
+        let close_start = end - tag.len() - 3;
+        found.push((tag, &html[start + 1..open_end], &html[open_end + 1..close_start.max(open_end + 1)]));
+        pos = end;
+    }
+    found
+}
+
+pub fn parse_table(html: &str) -> Option<Table> {
+    let caption = children(html, &["caption"]).first().map(|(_, _, inner)| plain_text(inner)).filter(|c| !c.is_empty());
+
+    let mut rows = Vec::new();
+    for (_, _, row_html) in children(html, &["tr"]) {
+        let cells: Vec<Cell> = children(row_html, &["th", "td"]).into_iter().map(|(tag, tag_content, inner)| {
+            let style = attr(tag_content, "style").unwrap_or("").replace(' ', "");
+            let header = tag == "th";
+            let align = if style.contains("text-align:right") || attr(tag_content, "align") == Some("right") {
+                Align::Right
+            } else if style.contains("text-align:center") || attr(tag_content, "align") == Some("center") || header {
+                Align::Center
+            } else {
+                Align::Left
+            };
+            let span = |name: &str| attr(tag_content, name).and_then(|v| v.trim().parse::<usize>().ok()).unwrap_or(1).clamp(1, 64);
+            Cell { text: plain_text(inner), header, align, colspan: span("colspan"), rowspan: span("rowspan") }
+        }).collect();
+        if !cells.is_empty() { rows.push(cells); }
+    }
+
+    if rows.is_empty() { return None; }
+    Some(layout(caption, &rows))
+}
+
+pub fn key_values(html: &str) -> Vec<(String, String)> {
+    children(html, &["tr"]).into_iter().filter_map(|(_, _, row_html)| {
+        match children(row_html, &["th", "td"]).as_slice() {
+            [("th", _, key), ("td", _, value)] => Some((plain_text(key), plain_text(value))),
+            _ => None,
+        }
+    }).filter(|(key, value)| !key.is_empty() && !value.is_empty()).collect()
+}
+
+struct Placed<'a> {
+    cell: &'a Cell,
+    row: usize,
+    col: usize,
+}
+
+fn layout(caption: Option<String>, rows: &[Vec<Cell>]) -> Table {
+    let mut occupied: Vec<Vec<bool>> = Vec::new();
+    let mut placed = Vec::new();
+    for (r, row) in rows.iter().enumerate() {
+        let mut c = 0;
+        for cell in row {
+            while occupied.get(r).and_then(|o| o.get(c)).copied().unwrap_or(false) { c += 1; }
+            for rr in r..(r + cell.rowspan).min(rows.len()) {
+                if occupied.len() <= rr { occupied.resize(rr + 1, Vec::new()); }
+                let needed = c + cell.colspan;
+                if occupied[rr].len() < needed { occupied[rr].resize(needed, false); }
+                occupied[rr][c..needed].fill(true);
+            }
+            placed.push(Placed { cell, row: r, col: c });
+            c += cell.colspan;
+        }
+    }
+    let n_rows = rows.len();
+    let n_cols = occupied.iter().map(|o| o.len()).max().unwrap_or(0).max(1);
+
+    let mut grid = vec![vec![String::new(); n_cols]; n_rows];
+    for p in &placed { grid[p.row][p.col] = p.cell.text.clone(); }
+
+    let text_width = |cell: &Cell| cell.text.width().clamp(1, MAX_COLUMN_WIDTH);
+
+    let mut col_widths = vec![1usize; n_cols];
+    for p in placed.iter().filter(|p| p.cell.colspan == 1) {
+        col_widths[p.col] = col_widths[p.col].max(text_width(p.cell));
+    }
+    for p in placed.iter().filter(|p| p.cell.colspan > 1) {
+        let end = (p.col + p.cell.colspan).min(n_cols);
+        let available: usize = col_widths[p.col..end].iter().sum::<usize>() + 3 * (end - p.col - 1);
+        let needed = text_width(p.cell);
+        if needed > available {
+            let extra = needed - available;
+            let span = end - p.col;
+            for (i, w) in col_widths[p.col..end].iter_mut().enumerate() {
+                *w += extra / span + usize::from(i < extra % span);
+            }
+        }
+    }
+
+    let span_width = |col: usize, span: usize| {
+        let end = (col + span).min(n_cols);
+        col_widths[col..end].iter().sum::<usize>() + 3 * (end - col).saturating_sub(1)
+    };
+    let wrapped: Vec<Vec<String>> = placed.iter()
+        .map(|p| textwrap::wrap(&p.cell.text, span_width(p.col, p.cell.colspan).max(1)).into_iter().map(|l| l.into_owned()).collect())
+        .collect();
+
+    let mut row_heights = vec![1usize; n_rows];
+    for (p, lines) in placed.iter().zip(&wrapped).filter(|(p, _)| p.cell.rowspan == 1) {
+        row_heights[p.row] = row_heights[p.row].max(lines.len());
+    }
+    for (p, lines) in placed.iter().zip(&wrapped).filter(|(p, _)| p.cell.rowspan > 1) {
+        let end = (p.row + p.cell.rowspan).min(n_rows);
+        let available: usize = row_heights[p.row..end].iter().sum::<usize>() + (end - p.row - 1);
+        if lines.len() > available {
+            row_heights[end - 1] += lines.len() - available;
+        }
+    }
+
+    let mut col_x = vec![0usize; n_cols + 1];
+    for c in 0..n_cols { col_x[c + 1] = col_x[c] + col_widths[c] + 3; }
+    let mut row_y = vec![0usize; n_rows + 1];
+    for r in 0..n_rows { row_y[r + 1] = row_y[r] + row_heights[r] + 1; }
+
+    let width = col_x[n_cols] + 1;
+    let height = row_y[n_rows] + 1;
+    let mut chars = vec![vec![' '; width]; height];
+    let mut kinds = vec![vec![CellKind::Data; width]; height];
+    let mut hseg = vec![vec![false; width]; height];
+    let mut vseg = vec![vec![false; width]; height];
+
+    for (p, lines) in placed.iter().zip(&wrapped) {
+        let x0 = col_x[p.col];
+        let x1 = col_x[(p.col + p.cell.colspan).min(n_cols)];
+        let y0 = row_y[p.row];
+        let y1 = row_y[(p.row + p.cell.rowspan).min(n_rows)];
+        hseg[y0][x0..x1].fill(true);
+        hseg[y1][x0..x1].fill(true);
+        for row in &mut vseg[y0..y1] { row[x0] = true; row[x1] = true; }
+
+        let kind = if p.cell.header { CellKind::Header } else { CellKind::Data };
+        let inner = x1 - x0 - 3;
+        for row in &mut kinds[y0 + 1..y1] { row[x0 + 1..x1].fill(kind); }
+        for (i, line) in lines.iter().enumerate() {
+            let len = line.width().min(inner);
+            let pad = match p.cell.align {
+                Align::Left => 0,
+                Align::Center => (inner - len) / 2,
+                Align::Right => inner - len,
+            };
+            let mut x = x0 + 2 + pad;
+            for ch in line.chars() {
+                let w = ch.width().unwrap_or(0);
+                if w == 0 { continue; }
+                if x + w > x0 + 2 + inner { break; }
+                chars[y0 + 1 + i][x] = ch;
+                chars[y0 + 1 + i][x + 1..x + w].fill(WIDE_FILLER);
+                x += w;
+            }
+        }
+    }
+
+    for y in 0..height {
+        for x in 0..width {
+            let left = x > 0 && hseg[y][x - 1];
+            let right = hseg[y][x];
+            let up = y > 0 && vseg[y - 1][x];
+            let down = vseg[y][x];
+            let ch = match (up, down, left, right) {
+                (false, false, false, false) => continue,
+                (false, false, _, _) => '─',
+                (_, _, false, false) => '│',
+                (false, true, false, true) => '┌',
+                (false, true, true, false) => '┐',
+                (true, false, false, true) => '└',
+                (true, false, true, false) => '┘',
+                (true, true, false, true) => '├',
+                (true, true, true, false) => '┤',
+                (false, true, true, true) => '┬',
+                (true, false, true, true) => '┴',
+                (true, true, true, true) => '┼',
+            };
+            chars[y][x] = ch;
+            kinds[y][x] = CellKind::Border;
+        }
+    }
+
+    let lines = chars.into_iter().zip(kinds).map(|(row, row_kinds)| {
+        let mut spans: Vec<(String, CellKind)> = Vec::new();
+        for (ch, kind) in row.into_iter().zip(row_kinds).filter(|(ch, _)| *ch != WIDE_FILLER) {
+            match spans.last_mut() {
+                Some((text, k)) if *k == kind => text.push(ch),
+                _ => spans.push((ch.to_string(), kind)),
+            }
+        }
+        spans
+    }).collect();
+
+    Table { caption, lines, width, grid }
+}
+
+pub fn styled_line(line: &[(String, CellKind)], h_scroll: usize, width: usize, theme: Color) -> Line<'static> {
+    let spans: Vec<(String, Style)> = line.iter().map(|(text, kind)| {
+        let style = match kind {
+            CellKind::Border => Style::default().fg(Color::DarkGray),
+            CellKind::Header => Style::default().fg(theme).add_modifier(Modifier::BOLD),
+            CellKind::Data => Style::default(),
+        };
+        (text.clone(), style)
+    }).collect();
+    text::styled_line(&spans, h_scroll, width)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn line_text(line: &[(String, CellKind)]) -> String {
+        line.iter().map(|(text, _)| text.as_str()).collect()
+    }
+
+    #[test]
+    fn columns_fit_the_widest_cell() {
+        let table = parse_table("<table><tr><th>City</th><th>Population</th></tr><tr><td>Berlin</td><td>3,878,100</td></tr></table>").unwrap();
+        assert_eq!(table.width, 1 + (6 + 3) + (10 + 3));
+        assert_eq!(line_text(&table.lines[0]), "┌────────┬────────────┐");
+        assert_eq!(line_text(&table.lines[3]), "│ Berlin │ 3,878,100  │");
+    }
+
+    #[test]
+    fn wide_characters_count_by_display_width() {
+        let table = parse_table("<table><tr><td>東京</td><td>x</td></tr><tr><td>Tokyo</td><td>y</td></tr></table>").unwrap();
+        assert_eq!(table.width, 1 + (5 + 3) + (1 + 3));
+        for line in &table.lines {
+            assert_eq!(line_text(line).width(), table.width);
+        }
+        assert_eq!(line_text(&table.lines[1]), "│ 東京  │ x │");
+    }
+
+    #[test]
+    fn colspan_widens_the_spanned_columns() {
+        let table = parse_table("<table><tr><td colspan=\"2\">a long heading</td></tr><tr><td>a</td><td>b</td></tr></table>").unwrap();
+        assert_eq!(table.width, 1 + 14 + 3);
+        for line in &table.lines {
+            assert_eq!(line_text(line).width(), table.width);
+        }
+    }
+
+    #[test]
+    fn scrolling_splits_wide_characters_into_spaces() {
+        let line = vec![("東京".to_string(), CellKind::Data)];
+        assert_eq!(styled_line(&line, 1, 10, Color::Reset).to_string(), " 京");
+        assert_eq!(styled_line(&line, 0, 3, Color::Reset).to_string(), "東");
+    }
+}
@@ -0,0 +1,335 @@
+use html2text::render::text_renderer::{RichAnnotation, TaggedLine, TaggedLineElement, TextDecorator};
+use ratatui::prelude::*;
+use unicode_width::{UnicodeWidthChar, UnicodeWidthStr};
+
+pub const CITATION_PREFIX: &str = "#cite:";
+
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct InlineStyle {
+    pub bold: bool,
+    pub italic: bool,
+    pub code: bool,
+    pub strike: bool,
+    pub link: Option<String>,
+    pub citation: Option<String>,
+}
+
+impl InlineStyle {
+    fn from_annotations(annotations: &[RichAnnotation]) -> Self {
+        let mut style = InlineStyle::default();
+        for annotation in annotations {
+            match annotation {
+                RichAnnotation::Strong => style.bold = true,
+                RichAnnotation::Emphasis => style.italic = true,
+                RichAnnotation::Code | RichAnnotation::Preformat(_) => style.code = true,
+                RichAnnotation::Strikeout => style.strike = true,
+                RichAnnotation::Link(target) => match target.strip_prefix(CITATION_PREFIX) {
+                    Some(label) => style.citation = Some(label.to_string()),
+                    None => style.link = Some(target.clone()),
+                },
+                _ => {}
+            }
+        }
+        style
+    }
+
+    pub fn to_style(&self) -> Style {
+        let mut style = Style::default();
+        if self.bold { style = style.add_modifier(Modifier::BOLD); }
+        if self.italic { style = style.add_modifier(Modifier::ITALIC); }
+        if self.strike { style = style.add_modifier(Modifier::CROSSED_OUT); }
+        if self.code { style = style.fg(Color::Cyan); }
+        style
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum LineKind {
+    Paragraph,
+    Header(usize),
+    ListItem { indent: usize, marker: String },
+    Quote(usize),
+}
+
+#[derive(Clone, Debug)]
+pub struct TextLine {
+    pub kind: LineKind,
+    pub spans: Vec<(String, InlineStyle)>,
+}
+
+impl TextLine {
+    pub fn plain(&self) -> String {
+        self.spans.iter().map(|(text, _)| text.as_str()).collect()
+    }
+
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct ArticleDecorator;
+
+impl TextDecorator for ArticleDecorator {
+    type Annotation = RichAnnotation;
+
+    fn decorate_link_start(&mut self, url: &str) -> (String, Self::Annotation) { (String::new(), RichAnnotation::Link(url.to_string())) }
+    fn decorate_link_end(&mut self) -> String { String::new() }
+    fn decorate_em_start(&mut self) -> (String, Self::Annotation) { (String::new(), RichAnnotation::Emphasis) }
+    fn decorate_em_end(&mut self) -> String { String::new() }
+    fn decorate_strong_start(&mut self) -> (String, Self::Annotation) { (String::new(), RichAnnotation::Strong) }
+    fn decorate_strong_end(&mut self) -> String { String::new() }
+    fn decorate_strikeout_start(&mut self) -> (String, Self::Annotation) { (String::new(), RichAnnotation::Strikeout) }
+    fn decorate_strikeout_end(&mut self) -> String { String::new() }
+    fn decorate_code_start(&mut self) -> (String, Self::Annotation) { (String::new(), RichAnnotation::Code) }
+    fn decorate_code_end(&mut self) -> String { String::new() }
+    fn decorate_preformat_first(&mut self) -> Self::Annotation { RichAnnotation::Preformat(false) }
+    fn decorate_preformat_cont(&mut self) -> Self::Annotation { RichAnnotation::Preformat(true) }
+    fn decorate_image(&mut self, src: &str, title: &str) -> (String, Self::Annotation) { (title.to_string(), RichAnnotation::Image(src.to_string())) }
+    fn header_prefix(&mut self, level: usize) -> String { "#".repeat(level) + " " }
+    fn quote_prefix(&mut self) -> String { "> ".to_string() }
+    fn unordered_item_prefix(&mut self) -> String { "* ".to_string() }
+    fn ordered_item_prefix(&mut self, i: i64) -> String { format!("{}. ", i) }
+    fn make_subblock_decorator(&self) -> Self { ArticleDecorator }
+    fn finalise(&mut self, _links: Vec<String>) -> Vec<TaggedLine<RichAnnotation>> { Vec::new() }
+}
+
+pub fn rich_lines(html: &str) -> Vec<TextLine> {
+    let html = html.replace("<b>", "<strong>").replace("</b>", "</strong>");
+    let lines = html2text::config::with_decorator(ArticleDecorator)
+        .lines_from_read(html.as_bytes(), 10000)
+        .unwrap_or_default();
+
+    lines.into_iter().map(|tagged| {
+        let mut kind = LineKind::Paragraph;
+        let mut indent = 0;
+        let mut quote_depth = 0;
+        let mut leading = true;
+        let mut spans: Vec<(String, InlineStyle)> = Vec::new();
+
+        for element in tagged.iter() {
+            let TaggedLineElement::Str(ts) = element else { continue; };
+            if leading && ts.tag.is_empty() {
+                let s = ts.s.as_str();
+                let hashes = s.chars().take_while(|&c| c == '#').count();
+                let digits = s.chars().take_while(|c| c.is_ascii_digit()).count();
+                if hashes > 0 && s.len() == hashes + 1 && s.ends_with(' ') {
+                    kind = LineKind::Header(hashes);
+                    continue;
+                } else if s == "> " {
+                    quote_depth += 1;
+                    continue;
+                } else if !s.is_empty() && s.chars().all(|c| c == ' ') {
+                    indent += s.len();
+                    continue;
+                } else if s == "* " || (digits > 0 && &s[digits..] == ". ") {
+                    let marker = if s == "* " { "•".to_string() } else { s.trim_end().to_string() };
+                    kind = LineKind::ListItem { indent, marker };
+                    continue;
+                }
+            }
+            leading = false;
+            let style = InlineStyle::from_annotations(&ts.tag);
+            match spans.last_mut() {
+                Some((text, last)) if *last == style => text.push_str(&ts.s),
+                _ => spans.push((ts.s.clone(), style)),
+            }
+        }
+
+        if quote_depth > 0 && matches!(kind, LineKind::Paragraph) {
+            kind = LineKind::Quote(quote_depth);
+        } else if indent > 0 && matches!(kind, LineKind::Paragraph) {
+            kind = LineKind::ListItem { indent, marker: String::new() };
+        }
+        TextLine { kind, spans }
+    }).collect()
+}
+
+pub fn indent_width(kind: &LineKind) -> usize {
+    match kind {
+        LineKind::ListItem { indent, marker } => indent + marker.width() + usize::from(!marker.is_empty()),
+        LineKind::Quote(depth) => 2 + 2 * depth,
+        _ => 0,
+    }
+}
+
+pub fn wrap_line(line: &TextLine, width: usize, theme: Color) -> Vec<Line<'static>> {
+    let quote_style = Style::default().fg(Color::DarkGray);
+    let (first_prefix, cont_prefix, base_style) = match &line.kind {
+        LineKind::Paragraph => (Vec::new(), Vec::new(), Style::default()),
+        LineKind::Header(_) => (Vec::new(), Vec::new(), Style::default().fg(theme).add_modifier(Modifier::BOLD)),
+        LineKind::ListItem { indent, marker } => {
+            let indent = " ".repeat(*indent);
+            let hang = " ".repeat(marker.width() + usize::from(!marker.is_empty()));
+            let first = if marker.is_empty() { indent.clone() } else { format!("{}{} ", indent, marker) };
+            (vec![Span::styled(first, Style::default().fg(theme))], vec![Span::raw(format!("{}{}", indent, hang))], Style::default())
+        }
+        LineKind::Quote(depth) => {
+            let bar = Span::styled("│ ".repeat(*depth), quote_style);
+            (vec![Span::raw("  "), bar.clone()], vec![Span::raw("  "), bar], Style::default().add_modifier(Modifier::ITALIC))
+        }
+    };
+
+    let prefix_width = |prefix: &Vec<Span>| prefix.iter().map(|s| s.width()).sum::<usize>();
+    let mut lines = Vec::new();
+    let mut current: Vec<Span<'static>> = first_prefix.clone();
+    let mut current_width = prefix_width(&first_prefix);
+    let mut line_start = true;
+    let cont_width = prefix_width(&cont_prefix);
+
+    let words = line.spans.iter().flat_map(|(text, style)| {
+        let style = base_style.patch(style.to_style());
+        split_words(text).into_iter().map(move |w| (w, style))
+    });
+
+    for (word, style) in words {
+        let is_space = word.chars().all(char::is_whitespace);
+        if line_start && is_space { continue; }
+        let word_width = word.width();
+        if current_width + word_width > width && !line_start {
+            trim_end(&mut current);
+            lines.push(Line::from(std::mem::replace(&mut current, cont_prefix.clone())));
+            current_width = cont_width;
+            line_start = true;
+            if is_space { continue; }
+        }
+        let mut word = word;
+        while current_width + word.width() > width && line_start && word.chars().count() > 1 {
+            let fit = width.saturating_sub(current_width).max(1);
+            let head: String = word.chars().take(fit).collect();
+            let tail: String = word.chars().skip(fit).collect();
+            current.push(Span::styled(head, style));
+            lines.push(Line::from(std::mem::replace(&mut current, cont_prefix.clone())));
+            current_width = cont_width;
+            word = tail;
+        }
+        current_width += word.width();
+        push_span(&mut current, word, style);
+        line_start = false;
+    }
+    if !line_start || lines.is_empty() {
+        lines.push(Line::from(current));
+    }
+    lines
+}
+
+fn trim_end(spans: &mut [Span<'static>]) {
+    if let Some(last) = spans.last_mut() {
+        let trimmed = last.content.trim_end().len();
+        last.content.to_mut().truncate(trimmed);
+    }
+}
+
+fn push_span(spans: &mut Vec<Span<'static>>, text: String, style: Style) {
+    match spans.last_mut() {
+        Some(last) if last.style == style => last.content.to_mut().push_str(&text),
+        _ => spans.push(Span::styled(text, style)),
+    }
+}
+
+fn split_words(text: &str) -> Vec<String> {
+    let mut words = Vec::new();
+    let mut current = String::new();
+    let mut in_space = false;
+    for c in text.chars() {
+        let space = c.is_whitespace();
+        if space != in_space && !current.is_empty() {
+            words.push(std::mem::take(&mut current));
+        }
+        in_space = space;
+        current.push(c);
+    }
+    if !current.is_empty() { words.push(current); }
+    words
+}
+
+pub fn styled_line(line: &[(String, Style)], h_scroll: usize, width: usize) -> Line<'static> {
+    let mut skip = h_scroll;
+    let mut remaining = width;
+    let mut spans = Vec::new();
+    for (text, style) in line {
+        if remaining == 0 { break; }
+        let len = text.width();
+        if skip >= len { skip -= len; continue; }
+        let mut visible = String::new();
+        for ch in text.chars() {
+            let w = ch.width().unwrap_or(0);
+            if skip > 0 {
+                let hidden = w.min(skip);
+                skip -= hidden;
+                visible.extend(std::iter::repeat_n(' ', (w - hidden).min(remaining)));
+                remaining -= (w - hidden).min(remaining);
+                continue;
+            }
+            if w > remaining { remaining = 0; break; }
+            visible.push(ch);
+            remaining -= w;
+        }
+        spans.push(Span::styled(visible, *style));
+    }
+    Line::from(spans)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn citations_are_marked_apart_from_superscripts() {
+        let lines = rich_lines("<p>E = mc² is cited<a href=\"#cite:note 1\">ⁿᵒᵗᵉ ¹</a> and <a href=\"./Energy\">linked</a></p>");
+        let spans = &lines[0].spans;
+        let citations: Vec<_> = spans.iter().filter_map(|(text, style)| Some((text.as_str(), style.citation.as_deref()?))).collect();
+        assert_eq!(citations, [("ⁿᵒᵗᵉ ¹", "note 1")]);
+        assert!(spans.iter().any(|(text, style)| text == "linked" && style.link.as_deref() == Some("./Energy")));
+        assert!(spans.iter().all(|(text, style)| !text.contains('²') || style.citation.is_none()));
+    }
+
+    fn rendered(lines: Vec<Line<'static>>) -> Vec<Vec<(String, Style)>> {
+        lines.into_iter().map(|line| line.spans.into_iter().map(|span| (span.content.into_owned(), span.style)).collect()).collect()
+    }
+
+    #[test]
+    fn nested_lists_indent_under_their_parent() {
+        let items: Vec<_> = rich_lines("<ul><li>One<ul><li>Two<ol><li>Three</li></ol></li></ul></li></ul>")
+            .into_iter()
+            .filter(|line| !line.plain().is_empty())
+            .map(|line| (line.plain(), indent_width(&line.kind), line.kind))
+            .collect();
+        assert_eq!(items, [
+            ("One".to_string(), 2, LineKind::ListItem { indent: 0, marker: "•".to_string() }),
+            ("Two".to_string(), 4, LineKind::ListItem { indent: 2, marker: "•".to_string() }),
+            ("Three".to_string(), 7, LineKind::ListItem { indent: 4, marker: "1.".to_string() }),
+        ]);
+        let three = TextLine { kind: LineKind::ListItem { indent: 4, marker: "1.".to_string() }, spans: vec![("Three four".to_string(), InlineStyle::default())] };
+        assert_eq!(rendered(wrap_line(&three, 12, Color::Blue)), [
+            vec![("    1. ".to_string(), Style::default().fg(Color::Blue)), ("Three".to_string(), Style::default())],
+            vec![("       four".to_string(), Style::default())],
+        ]);
+    }
+
+    #[test]
+    fn blockquotes_repeat_their_bars_on_every_line() {
+        let quotes: Vec<_> = rich_lines("<blockquote><p>Quoted here</p><blockquote><p>Deeper</p></blockquote></blockquote>")
+            .into_iter()
+            .filter(|line| !line.plain().is_empty())
+            .collect();
+        assert_eq!(quotes.iter().map(|line| &line.kind).collect::<Vec<_>>(), [&LineKind::Quote(1), &LineKind::Quote(2)]);
+        assert_eq!(indent_width(&quotes[1].kind), 6);
+        let (gap, bar, text) = (Style::default(), Style::default().fg(Color::DarkGray), Style::default().add_modifier(Modifier::ITALIC));
+        assert_eq!(rendered(wrap_line(&quotes[0], 10, Color::Blue)), [
+            vec![("  ".to_string(), gap), ("│ ".to_string(), bar), ("Quoted".to_string(), text)],
+            vec![("  ".to_string(), gap), ("│ ".to_string(), bar), ("here".to_string(), text)],
+        ]);
+        assert_eq!(rendered(wrap_line(&quotes[1], 20, Color::Blue)), [
+            vec![("  ".to_string(), gap), ("│ │ ".to_string(), bar), ("Deeper".to_string(), text)],
+        ]);
+    }
+
+    #[test]
+    fn styled_spans_keep_their_style_across_a_wrap() {
+        let bold = InlineStyle { bold: true, ..InlineStyle::default() };
+        let line = TextLine { kind: LineKind::Paragraph, spans: vec![("plain then ".to_string(), InlineStyle::default()), ("bold across lines".to_string(), bold)] };
+        let strong = Style::default().add_modifier(Modifier::BOLD);
+        assert_eq!(rendered(wrap_line(&line, 16, Color::Blue)), [
+            vec![("plain then ".to_string(), Style::default()), ("bold".to_string(), strong)],
+            vec![("across lines".to_string(), strong)],
+        ]);
+    }
+}